@@ -1,17 +1,27 @@
-use std::{collections::HashMap, fmt}; // for Display trait; TODO
-use tokens::Token;
+#![allow(clippy::needless_return)]
 
+use std::{fmt, rc::Rc};
+use tokens::{OwnedToken, Radix};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ASTNodeType {
+    Program,
     Statement,
     Expression,
     Identifier,
-    PostIncrementIdentifier,
+    UpdateExpression,
     LetStatement,
     ReturnStatement,
+    ForStatement,
+    ForInStatement,
+    WhileStatement,
+    BreakStatement,
+    ContinueStatement,
     ExpressionStatement,
     PrefixExpression,
     InfixExpression,
     IntegerLiteral,
+    FloatLiteral,
     BooleanLiteral,
     IfExpression,
     BlockStatement,
@@ -19,6 +29,7 @@ pub enum ASTNodeType {
     CallExpression,
     StringLiteral,
     AssignmentStatement,
+    CompoundAssignmentStatement,
     ArrayLiteral,
     IndexExpression,
     ArrayIndexExpression,
@@ -30,13 +41,14 @@ pub trait ASTNode {
     fn token_literal(&self) -> &str;
 }
 
+#[derive(Debug, Clone)]
 pub struct Identifier {
-    pub token: Token,
+    pub token: OwnedToken,
     pub value: String,
 }
 
 impl Identifier {
-    pub fn new(token: Token, value: String) -> Self {
+    pub fn new(token: OwnedToken, value: String) -> Self {
         Self { token, value }
     }
 }
@@ -57,57 +69,208 @@ impl fmt::Display for Identifier {
     }
 }
 
-pub struct Expression {}
+#[derive(Debug, Clone)]
+pub enum Expression {
+    Identifier(Identifier),
+    Integer(IntegerLiteral),
+    Float(FloatLiteral),
+    Boolean(BooleanLiteral),
+    String(StringLiteral),
+    Prefix(Box<PrefixExpression>),
+    Infix(Box<InfixExpression>),
+    Update(Box<UpdateExpression>),
+    If(Box<IfExpression>),
+    // Shared so function values can hold on to their literal cheaply
+    Function(Rc<FunctionLiteral>),
+    Call(Box<CallExpression>),
+    Array(ArrayLiteral),
+    Hash(HashLiteral),
+    Index(Box<IndexExpression>),
+}
+
+impl Expression {
+    fn node(&self) -> &dyn ASTNode {
+        return match self {
+            Expression::Identifier(node) => node,
+            Expression::Integer(node) => node,
+            Expression::Float(node) => node,
+            Expression::Boolean(node) => node,
+            Expression::String(node) => node,
+            Expression::Prefix(node) => node.as_ref(),
+            Expression::Infix(node) => node.as_ref(),
+            Expression::Update(node) => node.as_ref(),
+            Expression::If(node) => node.as_ref(),
+            Expression::Function(node) => node.as_ref(),
+            Expression::Call(node) => node.as_ref(),
+            Expression::Array(node) => node,
+            Expression::Hash(node) => node,
+            Expression::Index(node) => node.as_ref(),
+        };
+    }
+
+    /// The variable an assignment target writes through: `a` for `a`,
+    /// `a[i]` and `a[i][j]`, and None for anything else.
+    pub fn root_identifier(&self) -> Option<&Identifier> {
+        return match self {
+            Expression::Identifier(identifier) => Some(identifier),
+            Expression::Index(index) => index.left.root_identifier(),
+            _ => None,
+        };
+    }
+}
 
 impl ASTNode for Expression {
     fn kind(&self) -> ASTNodeType {
-        return ASTNodeType::Expression;
+        return self.node().kind();
     }
 
     fn token_literal(&self) -> &str {
-        unimplemented!();
+        return self.node().token_literal();
     }
 }
 
 impl fmt::Display for Expression {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Expression")
+        match self {
+            Expression::Identifier(node) => write!(f, "{}", node),
+            Expression::Integer(node) => write!(f, "{}", node),
+            Expression::Float(node) => write!(f, "{}", node),
+            Expression::Boolean(node) => write!(f, "{}", node),
+            Expression::String(node) => write!(f, "{}", node),
+            Expression::Prefix(node) => write!(f, "{}", node),
+            Expression::Infix(node) => write!(f, "{}", node),
+            Expression::Update(node) => write!(f, "{}", node),
+            Expression::If(node) => write!(f, "{}", node),
+            Expression::Function(node) => write!(f, "{}", node),
+            Expression::Call(node) => write!(f, "{}", node),
+            Expression::Array(node) => write!(f, "{}", node),
+            Expression::Hash(node) => write!(f, "{}", node),
+            Expression::Index(node) => write!(f, "{}", node),
+        }
     }
 }
 
-pub struct Statement {}
+#[derive(Debug, Clone)]
+pub enum Statement {
+    Let(LetStatement),
+    Return(ReturnStatement),
+    Expression(ExpressionStatement),
+    Assignment(AssignmentStatement),
+    CompoundAssignment(CompoundAssignmentStatement),
+    IndexAssignment(ArrayIndexExpression),
+    For(ForStatement),
+    ForIn(ForInStatement),
+    While(WhileStatement),
+    Break(BreakStatement),
+    Continue(ContinueStatement),
+}
+
+impl Statement {
+    fn node(&self) -> &dyn ASTNode {
+        return match self {
+            Statement::Let(node) => node,
+            Statement::Return(node) => node,
+            Statement::Expression(node) => node,
+            Statement::Assignment(node) => node,
+            Statement::CompoundAssignment(node) => node,
+            Statement::IndexAssignment(node) => node,
+            Statement::For(node) => node,
+            Statement::ForIn(node) => node,
+            Statement::While(node) => node,
+            Statement::Break(node) => node,
+            Statement::Continue(node) => node,
+        };
+    }
+}
 
 impl ASTNode for Statement {
     fn kind(&self) -> ASTNodeType {
-        return ASTNodeType::Statement;
+        return self.node().kind();
     }
 
     fn token_literal(&self) -> &str {
-        unimplemented!();
+        return self.node().token_literal();
     }
 }
 
 impl fmt::Display for Statement {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "Statement")
+        match self {
+            Statement::Let(node) => write!(f, "{}", node),
+            Statement::Return(node) => write!(f, "{}", node),
+            Statement::Expression(node) => write!(f, "{}", node),
+            Statement::Assignment(node) => write!(f, "{}", node),
+            Statement::CompoundAssignment(node) => write!(f, "{}", node),
+            Statement::IndexAssignment(node) => write!(f, "{}", node),
+            Statement::For(node) => write!(f, "{}", node),
+            Statement::ForIn(node) => write!(f, "{}", node),
+            Statement::While(node) => write!(f, "{}", node),
+            Statement::Break(node) => write!(f, "{}", node),
+            Statement::Continue(node) => write!(f, "{}", node),
+        }
     }
 }
 
-pub struct PostIncrementIdentifier {
-    pub token: Token,
-    pub value: String,
-    pub operator: Token,
+/// The root of a parsed source file.
+#[derive(Debug, Clone, Default)]
+pub struct Program {
+    pub statements: Vec<Statement>,
+}
+
+impl Program {
+    pub fn new(statements: Vec<Statement>) -> Self {
+        Self { statements }
+    }
+}
+
+impl ASTNode for Program {
+    fn kind(&self) -> ASTNodeType {
+        return ASTNodeType::Program;
+    }
+
+    fn token_literal(&self) -> &str {
+        return match self.statements.first() {
+            Some(statement) => statement.token_literal(),
+            None => "",
+        };
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for statement in &self.statements {
+            write!(f, "{}", statement)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// `++target`, `--target`, `target++` or `target--`, where `target` is an
+/// identifier or index expression. The prefix forms evaluate to the updated
+/// value, the postfix forms to the value from before the update.
+#[derive(Debug, Clone)]
+pub struct UpdateExpression {
+    pub token: OwnedToken,
+    pub operator: String,
+    pub target: Expression,
+    pub prefix: bool,
 }
 
-impl PostIncrementIdentifier {
-    pub fn new(token: Token, value: String, operator: Token) -> Self {
-        Self { token, value, operator }
+impl UpdateExpression {
+    pub fn new(token: OwnedToken, operator: String, target: Expression, prefix: bool) -> Self {
+        Self {
+            token,
+            operator,
+            target,
+            prefix,
+        }
     }
 }
 
-impl ASTNode for PostIncrementIdentifier {
+impl ASTNode for UpdateExpression {
     fn kind(&self) -> ASTNodeType {
-        return ASTNodeType::PostIncrementIdentifier;
+        return ASTNodeType::UpdateExpression;
     }
 
     fn token_literal(&self) -> &str {
@@ -115,22 +278,32 @@ impl ASTNode for PostIncrementIdentifier {
     }
 }
 
-impl fmt::Display for PostIncrementIdentifier {
+impl fmt::Display for UpdateExpression {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}{}", self.value, self.operator.literal)
+        if self.prefix {
+            write!(f, "({}{})", self.operator, self.target)
+        } else {
+            write!(f, "({}{})", self.target, self.operator)
+        }
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct LetStatement {
-    pub token: Token,
+    pub token: OwnedToken,
     pub name: Identifier,
     pub value: Expression,
     pub constant: bool,
 }
 
 impl LetStatement {
-    pub fn new(token: Token, name: Identifier, value: Expression, constant: bool) -> Self {
-        Self { token, name, value, constant }
+    pub fn new(token: OwnedToken, name: Identifier, value: Expression, constant: bool) -> Self {
+        Self {
+            token,
+            name,
+            value,
+            constant,
+        }
     }
 }
 
@@ -156,14 +329,223 @@ impl fmt::Display for LetStatement {
     }
 }
 
+/// C-style `for (init; condition; update) { body }`, where each of the three
+/// clauses may be left empty. A missing condition loops until broken out of.
+#[derive(Debug, Clone)]
+pub struct ForStatement {
+    pub token: OwnedToken,
+    pub init: Option<Box<Statement>>,
+    pub condition: Option<Expression>,
+    pub update: Option<Box<Statement>>,
+    pub body: BlockStatement,
+}
+
+impl ForStatement {
+    pub fn new(
+        token: OwnedToken,
+        init: Option<Box<Statement>>,
+        condition: Option<Expression>,
+        update: Option<Box<Statement>>,
+        body: BlockStatement,
+    ) -> Self {
+        Self {
+            token,
+            init,
+            condition,
+            update,
+            body,
+        }
+    }
+}
+
+impl ASTNode for ForStatement {
+    fn kind(&self) -> ASTNodeType {
+        return ASTNodeType::ForStatement;
+    }
+
+    fn token_literal(&self) -> &str {
+        return &self.token.literal;
+    }
+}
+
+impl fmt::Display for ForStatement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let init = match &self.init {
+            // Let statements already print their own `;`
+            Some(init) => init.to_string().trim_end_matches(';').to_string(),
+            None => String::new(),
+        };
+        let condition = match &self.condition {
+            Some(condition) => condition.to_string(),
+            None => String::new(),
+        };
+        let update = match &self.update {
+            Some(update) => update.to_string(),
+            None => String::new(),
+        };
+
+        write!(
+            f,
+            "for ({}; {}; {}) {{ {} }}",
+            init, condition, update, self.body
+        )
+    }
+}
+
+/// `for (value in iterable) { body }` or `for (key, value in iterable) { body }`.
+/// Over an array `key` is the index and `value` the element, over a hash they
+/// are the pair. With a single name, a hash yields its keys.
+#[derive(Debug, Clone)]
+pub struct ForInStatement {
+    pub token: OwnedToken,
+    pub key: Option<Identifier>,
+    pub value: Identifier,
+    pub iterable: Expression,
+    pub body: BlockStatement,
+}
+
+impl ForInStatement {
+    pub fn new(
+        token: OwnedToken,
+        key: Option<Identifier>,
+        value: Identifier,
+        iterable: Expression,
+        body: BlockStatement,
+    ) -> Self {
+        Self {
+            token,
+            key,
+            value,
+            iterable,
+            body,
+        }
+    }
+}
+
+impl ASTNode for ForInStatement {
+    fn kind(&self) -> ASTNodeType {
+        return ASTNodeType::ForInStatement;
+    }
+
+    fn token_literal(&self) -> &str {
+        return &self.token.literal;
+    }
+}
+
+impl fmt::Display for ForInStatement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "for (")?;
+        if let Some(key) = &self.key {
+            write!(f, "{}, ", key)?;
+        }
+
+        write!(
+            f,
+            "{} in {}) {{ {} }}",
+            self.value, self.iterable, self.body
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WhileStatement {
+    pub token: OwnedToken,
+    pub condition: Expression,
+    pub body: BlockStatement,
+}
+
+impl WhileStatement {
+    pub fn new(token: OwnedToken, condition: Expression, body: BlockStatement) -> Self {
+        Self {
+            token,
+            condition,
+            body,
+        }
+    }
+}
+
+impl ASTNode for WhileStatement {
+    fn kind(&self) -> ASTNodeType {
+        return ASTNodeType::WhileStatement;
+    }
+
+    fn token_literal(&self) -> &str {
+        return &self.token.literal;
+    }
+}
+
+impl fmt::Display for WhileStatement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "while {} {{ {} }}", self.condition, self.body)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BreakStatement {
+    pub token: OwnedToken,
+}
+
+impl BreakStatement {
+    pub fn new(token: OwnedToken) -> Self {
+        Self { token }
+    }
+}
+
+impl ASTNode for BreakStatement {
+    fn kind(&self) -> ASTNodeType {
+        return ASTNodeType::BreakStatement;
+    }
+
+    fn token_literal(&self) -> &str {
+        return &self.token.literal;
+    }
+}
+
+impl fmt::Display for BreakStatement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{};", self.token_literal())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ContinueStatement {
+    pub token: OwnedToken,
+}
+
+impl ContinueStatement {
+    pub fn new(token: OwnedToken) -> Self {
+        Self { token }
+    }
+}
+
+impl ASTNode for ContinueStatement {
+    fn kind(&self) -> ASTNodeType {
+        return ASTNodeType::ContinueStatement;
+    }
+
+    fn token_literal(&self) -> &str {
+        return &self.token.literal;
+    }
+}
+
+impl fmt::Display for ContinueStatement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{};", self.token_literal())
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct ReturnStatement {
-    pub token: Token,
+    pub token: OwnedToken,
     pub return_value: Expression,
 }
 
 impl ReturnStatement {
-    pub fn new(token: Token, return_value: Expression) -> Self {
-        Self { token, return_value }
+    pub fn new(token: OwnedToken, return_value: Expression) -> Self {
+        Self {
+            token,
+            return_value,
+        }
     }
 }
 
@@ -183,13 +565,14 @@ impl fmt::Display for ReturnStatement {
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct ExpressionStatement {
-    pub token: Token,
+    pub token: OwnedToken,
     pub expression: Expression,
 }
 
 impl ExpressionStatement {
-    pub fn new(token: Token, expression: Expression) -> Self {
+    pub fn new(token: OwnedToken, expression: Expression) -> Self {
         Self { token, expression }
     }
 }
@@ -210,15 +593,20 @@ impl fmt::Display for ExpressionStatement {
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct PrefixExpression {
-    pub token: Token,
+    pub token: OwnedToken,
     pub operator: String,
     pub right: Expression,
 }
 
 impl PrefixExpression {
-    pub fn new(token: Token, operator: String, right: Expression) -> Self {
-        Self { token, operator, right }
+    pub fn new(token: OwnedToken, operator: String, right: Expression) -> Self {
+        Self {
+            token,
+            operator,
+            right,
+        }
     }
 }
 
@@ -238,16 +626,22 @@ impl fmt::Display for PrefixExpression {
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct InfixExpression {
-    pub token: Token,
+    pub token: OwnedToken,
     pub operator: String,
     pub right: Expression,
     pub left: Expression,
 }
 
 impl InfixExpression {
-    pub fn new(token: Token, operator: String, right: Expression, left: Expression) -> Self {
-        Self { token, operator, right, left }
+    pub fn new(token: OwnedToken, operator: String, right: Expression, left: Expression) -> Self {
+        Self {
+            token,
+            operator,
+            right,
+            left,
+        }
     }
 }
 
@@ -267,14 +661,20 @@ impl fmt::Display for InfixExpression {
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct IntegerLiteral {
-    pub token: Token,
+    pub token: OwnedToken,
     pub value: i64,
+    pub radix: Radix,
 }
 
 impl IntegerLiteral {
-    pub fn new(token: Token, value: i64) -> Self {
-        Self { token, value }
+    pub fn new(token: OwnedToken, value: i64, radix: Radix) -> Self {
+        Self {
+            token,
+            value,
+            radix,
+        }
     }
 }
 
@@ -294,13 +694,42 @@ impl fmt::Display for IntegerLiteral {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct FloatLiteral {
+    pub token: OwnedToken,
+    pub value: f64,
+}
+
+impl FloatLiteral {
+    pub fn new(token: OwnedToken, value: f64) -> Self {
+        Self { token, value }
+    }
+}
+
+impl ASTNode for FloatLiteral {
+    fn kind(&self) -> ASTNodeType {
+        return ASTNodeType::FloatLiteral;
+    }
+
+    fn token_literal(&self) -> &str {
+        return &self.token.literal;
+    }
+}
+
+impl fmt::Display for FloatLiteral {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.token.literal)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct BooleanLiteral {
-    pub token: Token,
+    pub token: OwnedToken,
     pub value: bool,
 }
 
 impl BooleanLiteral {
-    pub fn new(token: Token, value: bool) -> Self {
+    pub fn new(token: OwnedToken, value: bool) -> Self {
         Self { token, value }
     }
 }
@@ -321,16 +750,27 @@ impl fmt::Display for BooleanLiteral {
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct IfExpression {
-    pub token: Token,
+    pub token: OwnedToken,
     pub condition: Expression,
     pub consequence: BlockStatement,
     pub alternative: Option<BlockStatement>,
 }
 
 impl IfExpression {
-    pub fn new(token: Token, condition: Expression, consequence: BlockStatement, alternative: Option<BlockStatement>) -> Self {
-        Self { token, condition, consequence, alternative }
+    pub fn new(
+        token: OwnedToken,
+        condition: Expression,
+        consequence: BlockStatement,
+        alternative: Option<BlockStatement>,
+    ) -> Self {
+        Self {
+            token,
+            condition,
+            consequence,
+            alternative,
+        }
     }
 }
 
@@ -359,13 +799,14 @@ impl fmt::Display for IfExpression {
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct BlockStatement {
-    pub token: Token,
+    pub token: OwnedToken,
     pub statements: Vec<Statement>,
 }
 
 impl BlockStatement {
-    pub fn new(token: Token, statements: Vec<Statement>) -> Self {
+    pub fn new(token: OwnedToken, statements: Vec<Statement>) -> Self {
         Self { token, statements }
     }
 }
@@ -392,15 +833,20 @@ impl fmt::Display for BlockStatement {
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct FunctionLiteral {
-    pub token: Token,
+    pub token: OwnedToken,
     pub parameters: Vec<Identifier>,
     pub body: BlockStatement,
 }
 
 impl FunctionLiteral {
-    pub fn new(token: Token, parameters: Vec<Identifier>, body: BlockStatement) -> Self {
-        Self { token, parameters, body }
+    pub fn new(token: OwnedToken, parameters: Vec<Identifier>, body: BlockStatement) -> Self {
+        Self {
+            token,
+            parameters,
+            body,
+        }
     }
 }
 
@@ -419,6 +865,9 @@ impl fmt::Display for FunctionLiteral {
         let mut params = String::new();
 
         for param in &self.parameters {
+            if !params.is_empty() {
+                params.push_str(", ");
+            }
             params.push_str(&param.to_string());
         }
 
@@ -426,15 +875,24 @@ impl fmt::Display for FunctionLiteral {
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct CallExpression {
-    pub token: Token,
+    pub token: OwnedToken,
     pub function: Expression,
     pub arguments: Option<Vec<Expression>>,
 }
 
 impl CallExpression {
-    pub fn new(token: Token, function: Expression, arguments: Option<Vec<Expression>>) -> Self {
-        Self { token, function, arguments }
+    pub fn new(
+        token: OwnedToken,
+        function: Expression,
+        arguments: Option<Vec<Expression>>,
+    ) -> Self {
+        Self {
+            token,
+            function,
+            arguments,
+        }
     }
 }
 
@@ -454,6 +912,9 @@ impl fmt::Display for CallExpression {
 
         if let Some(arguments) = &self.arguments {
             for arg in arguments {
+                if !args.is_empty() {
+                    args.push_str(", ");
+                }
                 args.push_str(&arg.to_string());
             }
         }
@@ -462,13 +923,14 @@ impl fmt::Display for CallExpression {
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct StringLiteral {
-    pub token: Token,
+    pub token: OwnedToken,
     pub value: String,
 }
 
 impl StringLiteral {
-    pub fn new(token: Token, value: String) -> Self {
+    pub fn new(token: OwnedToken, value: String) -> Self {
         Self { token, value }
     }
 }
@@ -489,14 +951,15 @@ impl fmt::Display for StringLiteral {
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct AssignmentStatement {
-    pub token: Token,
+    pub token: OwnedToken,
     pub name: Identifier,
     pub value: Expression,
 }
 
 impl AssignmentStatement {
-    pub fn new(token: Token, name: Identifier, value: Expression) -> Self {
+    pub fn new(token: OwnedToken, name: Identifier, value: Expression) -> Self {
         Self { token, name, value }
     }
 }
@@ -517,13 +980,52 @@ impl fmt::Display for AssignmentStatement {
     }
 }
 
+/// `target op= value`, where `target` is an identifier or an index
+/// expression. Kept apart from `AssignmentStatement` so the target is only
+/// evaluated once, and `operator` holds the arithmetic part, e.g. `+`.
+#[derive(Debug, Clone)]
+pub struct CompoundAssignmentStatement {
+    pub token: OwnedToken,
+    pub target: Expression,
+    pub operator: String,
+    pub value: Expression,
+}
+
+impl CompoundAssignmentStatement {
+    pub fn new(token: OwnedToken, target: Expression, operator: String, value: Expression) -> Self {
+        Self {
+            token,
+            target,
+            operator,
+            value,
+        }
+    }
+}
+
+impl ASTNode for CompoundAssignmentStatement {
+    fn kind(&self) -> ASTNodeType {
+        return ASTNodeType::CompoundAssignmentStatement;
+    }
+
+    fn token_literal(&self) -> &str {
+        return &self.token.literal;
+    }
+}
+
+impl fmt::Display for CompoundAssignmentStatement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}= {}", self.target, self.operator, self.value)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct ArrayLiteral {
-    pub token: Token,
+    pub token: OwnedToken,
     pub elements: Vec<Expression>,
 }
 
 impl ArrayLiteral {
-    pub fn new(token: Token, elements: Vec<Expression>) -> Self {
+    pub fn new(token: OwnedToken, elements: Vec<Expression>) -> Self {
         Self { token, elements }
     }
 }
@@ -543,6 +1045,9 @@ impl fmt::Display for ArrayLiteral {
         let mut elements = String::new();
 
         for elm in &self.elements {
+            if !elements.is_empty() {
+                elements.push_str(", ");
+            }
             elements.push_str(&elm.to_string());
         }
 
@@ -550,14 +1055,15 @@ impl fmt::Display for ArrayLiteral {
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct IndexExpression {
-    pub token: Token,
+    pub token: OwnedToken,
     pub left: Expression,
     pub index: Expression,
 }
 
 impl IndexExpression {
-    pub fn new(token: Token, left: Expression, index: Expression) -> Self {
+    pub fn new(token: OwnedToken, left: Expression, index: Expression) -> Self {
         Self { token, left, index }
     }
 }
@@ -578,16 +1084,22 @@ impl fmt::Display for IndexExpression {
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct ArrayIndexExpression {
-    pub token: Token,
+    pub token: OwnedToken,
     pub array: Expression,
     pub index: Expression,
     pub value: Expression,
 }
 
 impl ArrayIndexExpression {
-    pub fn new(token: Token, array: Expression, index: Expression, value: Expression) -> Self {
-        Self { token, array, index, value }
+    pub fn new(token: OwnedToken, array: Expression, index: Expression, value: Expression) -> Self {
+        Self {
+            token,
+            array,
+            index,
+            value,
+        }
     }
 }
 
@@ -607,13 +1119,16 @@ impl fmt::Display for ArrayIndexExpression {
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct HashLiteral {
-    pub token: Token,
-    pub pairs: HashMap<Expression, Expression>,
+    pub token: OwnedToken,
+    // A list rather than a map, as expressions are not hashable and the
+    // source order of the pairs is kept
+    pub pairs: Vec<(Expression, Expression)>,
 }
 
 impl HashLiteral {
-    pub fn new(token: Token, pairs: HashMap<Expression, Expression>) -> Self {
+    pub fn new(token: OwnedToken, pairs: Vec<(Expression, Expression)>) -> Self {
         Self { token, pairs }
     }
 }
@@ -630,6 +1145,85 @@ impl ASTNode for HashLiteral {
 
 impl fmt::Display for HashLiteral {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "[{}]", "undone")
+        let pairs: Vec<String> = self
+            .pairs
+            .iter()
+            .map(|(key, value)| format!("{}: {}", key, value))
+            .collect();
+
+        write!(f, "{{{}}}", pairs.join(", "))
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokens::TokenType;
+
+    fn identifier(name: &str) -> Identifier {
+        Identifier::new(
+            OwnedToken::new(TokenType::Ident, name.to_string()),
+            name.to_string(),
+        )
+    }
+
+    #[test]
+    fn it_displays_a_program() {
+        let program = Program::new(vec![
+            Statement::Let(LetStatement::new(
+                OwnedToken::new(TokenType::Let, "let"),
+                identifier("x"),
+                Expression::Infix(Box::new(InfixExpression::new(
+                    OwnedToken::new(TokenType::Plus, "+"),
+                    "+".to_string(),
+                    Expression::Integer(IntegerLiteral::new(
+                        OwnedToken::new(TokenType::Int, "0x10"),
+                        16,
+                        Radix::Hexadecimal,
+                    )),
+                    Expression::Identifier(identifier("y")),
+                ))),
+                true,
+            )),
+            Statement::Return(ReturnStatement::new(
+                OwnedToken::new(TokenType::Return, "return"),
+                Expression::Identifier(identifier("x")),
+            )),
+        ]);
+
+        assert_eq!(program.to_string(), "let const x = (y + 0x10);return x;");
+        assert_eq!(program.kind(), ASTNodeType::Program);
+        assert_eq!(program.token_literal(), "let");
+        assert_eq!(program.statements[1].kind(), ASTNodeType::ReturnStatement);
+    }
+    #[test]
+    fn it_keeps_hash_literal_pairs_in_source_order() {
+        let string = |value: &str| {
+            Expression::String(StringLiteral::new(
+                OwnedToken::new(TokenType::String, value.to_string()),
+                value.to_string(),
+            ))
+        };
+        let integer = |value: i64| {
+            Expression::Integer(IntegerLiteral::new(
+                OwnedToken::new(TokenType::Int, value.to_string()),
+                value,
+                Radix::Decimal,
+            ))
+        };
+
+        // Duplicate keys are kept, as only evaluation can tell they clash
+        let hash = HashLiteral::new(
+            OwnedToken::new(TokenType::LBrace, "{"),
+            vec![
+                (string("b"), integer(1)),
+                (string("a"), integer(2)),
+                (string("b"), integer(3)),
+            ],
+        );
+
+        assert_eq!(hash.to_string(), "{b: 1, a: 2, b: 3}");
+        assert_eq!(hash.kind(), ASTNodeType::HashLiteral);
+        assert_eq!(hash.token_literal(), "{");
+    }
+}
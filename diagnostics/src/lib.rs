@@ -0,0 +1,433 @@
+#![allow(clippy::needless_return)]
+
+use lexer::LexError;
+use parser::ParseError;
+use std::fmt::Write;
+use tokens::Span;
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const BOLD_RED: &str = "\x1b[1;31m";
+const BOLD_YELLOW: &str = "\x1b[1;33m";
+const BOLD_BLUE: &str = "\x1b[1;34m";
+const BOLD_CYAN: &str = "\x1b[1;36m";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn name(&self) -> &'static str {
+        return match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+    }
+
+    fn style(&self) -> &'static str {
+        return match self {
+            Severity::Error => BOLD_RED,
+            Severity::Warning => BOLD_YELLOW,
+        };
+    }
+}
+
+/// A span of source with a short note attached, drawn under the source line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+}
+
+/// A problem in some source, ready to be rendered rustc-style. The primary
+/// span is underlined with `^`, secondary labels with `-`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub primary: Label,
+    pub secondary: Vec<Label>,
+    pub help: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: impl Into<String>, span: Span) -> Diagnostic {
+        Diagnostic {
+            severity,
+            message: message.into(),
+            primary: Label {
+                span,
+                message: String::new(),
+            },
+            secondary: Vec::new(),
+            help: Vec::new(),
+        }
+    }
+
+    pub fn error(message: impl Into<String>, span: Span) -> Diagnostic {
+        return Diagnostic::new(Severity::Error, message, span);
+    }
+
+    pub fn warning(message: impl Into<String>, span: Span) -> Diagnostic {
+        return Diagnostic::new(Severity::Warning, message, span);
+    }
+
+    /// Sets the note drawn next to the primary span's carets.
+    pub fn with_label(mut self, message: impl Into<String>) -> Diagnostic {
+        self.primary.message = message.into();
+        return self;
+    }
+
+    pub fn with_secondary(mut self, span: Span, message: impl Into<String>) -> Diagnostic {
+        self.secondary.push(Label {
+            span,
+            message: message.into(),
+        });
+        return self;
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Diagnostic {
+        self.help.push(help.into());
+        return self;
+    }
+
+    /// Renders the diagnostic against the source it was found in, with ANSI
+    /// colours if `colour` is set. Labels with an unknown span are skipped.
+    pub fn render(&self, file_name: &str, source: &str, colour: bool) -> String {
+        let paint = |text: &str, style: &str| -> String {
+            if colour {
+                format!("{}{}{}", style, text, RESET)
+            } else {
+                text.to_string()
+            }
+        };
+
+        let mut labels = vec![(&self.primary, true)];
+        labels.extend(self.secondary.iter().map(|label| (label, false)));
+        labels.retain(|(label, _)| label.span.line > 0);
+        labels.sort_by_key(|(label, _)| (label.span.line, label.span.column));
+
+        let lines = source.lines().collect::<Vec<_>>();
+        let width = labels
+            .iter()
+            .map(|(label, _)| label.span.line.to_string().len())
+            .max()
+            .unwrap_or(0);
+        let gutter = " ".repeat(width);
+        let bar = paint("|", BOLD_BLUE);
+
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "{}{}",
+            paint(self.severity.name(), self.severity.style()),
+            paint(&format!(": {}", self.message), BOLD)
+        );
+
+        let location = match self.primary.span.line {
+            0 => file_name.to_string(),
+            _ => format!("{}:{}", file_name, self.primary.span),
+        };
+        let _ = writeln!(out, "{}{} {}", gutter, paint("-->", BOLD_BLUE), location);
+
+        if !labels.is_empty() {
+            let _ = writeln!(out, "{} {}", gutter, bar);
+        }
+
+        let mut last_line = None;
+        for (label, primary) in &labels {
+            let line = label.span.line;
+            // Input can end in a newline, leaving a span on an empty last line
+            let text = lines.get(line - 1).copied().unwrap_or("");
+
+            if last_line != Some(line) {
+                if last_line.is_some_and(|last| line > last + 1) {
+                    let _ = writeln!(out, "{}", paint("...", BOLD_BLUE));
+                }
+                let _ = writeln!(
+                    out,
+                    "{} {} {}",
+                    paint(&format!("{:>width$}", line), BOLD_BLUE),
+                    bar,
+                    text
+                );
+                last_line = Some(line);
+            }
+
+            // Mirror tabs so the markers line up however they are displayed
+            let indent = text
+                .chars()
+                .chain(std::iter::repeat(' '))
+                .take(label.span.column.saturating_sub(1))
+                .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+                .collect::<String>();
+
+            // Spans running past the end of the line are cut off there
+            let length = source
+                .get(label.span.start..label.span.end)
+                .map(|slice| slice.split('\n').next().unwrap_or("").chars().count())
+                .unwrap_or(0)
+                .max(1);
+
+            let (marker, style) = match primary {
+                true => ("^", self.severity.style()),
+                false => ("-", BOLD_BLUE),
+            };
+            let mut markers = marker.repeat(length);
+            if !label.message.is_empty() {
+                markers = format!("{} {}", markers, label.message);
+            }
+            let _ = writeln!(
+                out,
+                "{} {} {}{}",
+                gutter,
+                bar,
+                indent,
+                paint(&markers, style)
+            );
+        }
+
+        if !self.help.is_empty() && !labels.is_empty() {
+            let _ = writeln!(out, "{} {}", gutter, bar);
+        }
+        for help in &self.help {
+            let _ = writeln!(
+                out,
+                "{} {} {}",
+                gutter,
+                paint("=", BOLD_BLUE),
+                paint(&format!("help: {}", help), BOLD_CYAN)
+            );
+        }
+
+        return out;
+    }
+}
+
+impl From<&LexError> for Diagnostic {
+    fn from(err: &LexError) -> Diagnostic {
+        let diagnostic = Diagnostic::error(err.message(), err.span());
+
+        return match err {
+            LexError::UnexpectedCharacter { .. } => {
+                diagnostic.with_label("not valid anywhere in a program")
+            }
+            LexError::UnterminatedComment { .. } => diagnostic
+                .with_label("comment starts here")
+                .with_help("block comments nest, so every `/*` needs its own `*/`"),
+            LexError::UnterminatedString { .. } => diagnostic
+                .with_label("string starts here")
+                .with_help("add a closing quote"),
+            LexError::InvalidEscape { .. } => diagnostic
+                .with_label("unknown escape sequence")
+                .with_help(r#"valid escapes are \n, \t, \r, \0, \\, \", \' and \u{...}"#),
+            LexError::InvalidNumber { reason, .. } => diagnostic.with_label(*reason),
+        };
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(err: &ParseError) -> Diagnostic {
+        let diagnostic = Diagnostic::error(err.message(), err.span());
+
+        return match err {
+            ParseError::UnexpectedToken { found, .. } => {
+                diagnostic.with_label(format!("unexpected {}", found))
+            }
+            ParseError::NoPrefixParser { .. } => diagnostic.with_label("expected an expression"),
+            ParseError::InvalidInteger { radix, .. } => {
+                diagnostic.with_label(format!("not a valid {} integer", radix))
+            }
+            ParseError::InvalidFloat { .. } => diagnostic.with_label("not a valid float"),
+            ParseError::UnterminatedBlock { .. } => diagnostic
+                .with_label("this block is never closed")
+                .with_help("add a `}` to close it"),
+            ParseError::InvalidAssignmentTarget { .. } => diagnostic
+                .with_label("cannot assign here")
+                .with_help("only identifiers and index expressions like `a[i]` can be assigned to"),
+            ParseError::InvalidUpdateTarget { .. } => diagnostic
+                .with_label("cannot update this")
+                .with_help("only identifiers and index expressions like `a[i]` can be updated"),
+            ParseError::LoopControlOutsideLoop {
+                in_function: true, ..
+            } => diagnostic
+                .with_label("not inside a loop")
+                .with_help("loops do not reach into the bodies of functions defined in them"),
+            ParseError::LoopControlOutsideLoop { .. } => diagnostic.with_label("not inside a loop"),
+            ParseError::AssignToConstant { declared, .. } => diagnostic
+                .with_label("cannot modify a constant")
+                .with_secondary(*declared, "declared as a constant here")
+                .with_help("declare it with `let` instead of `let const` if it needs to change"),
+            ParseError::RedeclaredConstant { declared, .. } => diagnostic
+                .with_label("redeclared here")
+                .with_secondary(*declared, "first declared as a constant here")
+                .with_help("pick a different name, or shadow it inside a function"),
+            ParseError::Lex(err) => Diagnostic::from(err),
+        };
+    }
+}
+
+/// Renders every diagnostic in turn, separated by blank lines.
+pub fn render_all(
+    file_name: &str,
+    source: &str,
+    diagnostics: &[Diagnostic],
+    colour: bool,
+) -> String {
+    return diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.render(file_name, source, colour))
+        .collect::<Vec<_>>()
+        .join("\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lexer::Lexer;
+    use parser::Parser;
+
+    fn parse_errors(source: &str) -> Vec<Diagnostic> {
+        let mut parser = Parser::new(Lexer::new(source));
+        parser.parse_program();
+        return parser.errors().iter().map(Diagnostic::from).collect();
+    }
+
+    #[test]
+    fn it_renders_a_parse_error() {
+        let source = "let x = 5;\nlet y 10;\n";
+        let diagnostics = parse_errors(source);
+
+        assert_eq!(
+            diagnostics[0].render("main.mk", source, false),
+            "\
+error: Expected next token to be =, got Integer
+ --> main.mk:2:7
+  |
+2 | let y 10;
+  |       ^^ unexpected Integer
+"
+        );
+    }
+    #[test]
+    fn it_renders_secondary_labels_and_help() {
+        let source = "let const x = 1;\n\nx = 2;";
+        let diagnostic = Diagnostic::error(
+            "cannot assign twice to constant `x`",
+            Span::new(19, 20, 3, 3),
+        )
+        .with_label("cannot assign twice")
+        .with_secondary(Span::new(10, 11, 1, 11), "declared as a constant here")
+        .with_help("declare it with `let` instead");
+
+        assert_eq!(
+            diagnostic.render("main.mk", source, false),
+            "\
+error: cannot assign twice to constant `x`
+ --> main.mk:3:3
+  |
+1 | let const x = 1;
+  |           - declared as a constant here
+...
+3 | x = 2;
+  |   ^ cannot assign twice
+  |
+  = help: declare it with `let` instead
+"
+        );
+    }
+    #[test]
+    fn it_renders_lexer_errors_through_the_parser() {
+        let source = "\tlet s = \"a\\qb\";";
+        let diagnostics = parse_errors(source);
+
+        assert_eq!(
+            diagnostics[0].render("<repl>", source, false),
+            "\
+error: invalid escape sequence `\\q`
+ --> <repl>:1:12
+  |
+1 | \tlet s = \"a\\qb\";
+  | \t          ^^ unknown escape sequence
+  |
+  = help: valid escapes are \\n, \\t, \\r, \\0, \\\\, \\\", \\' and \\u{...}
+"
+        );
+    }
+    #[test]
+    fn it_cuts_multiline_spans_and_handles_unknown_spans() {
+        let source = "if x {\n  y";
+        let rendered = render_all("main.mk", source, &parse_errors(source), false);
+        assert!(rendered.contains("1 | if x {\n  |      ^ this block is never closed\n"));
+
+        let diagnostic = Diagnostic::warning("something odd", Span::default());
+        assert_eq!(
+            diagnostic.render("main.mk", source, false),
+            "warning: something odd\n--> main.mk\n"
+        );
+    }
+    #[test]
+    fn it_points_at_constant_declarations() {
+        let source = "let const limit = 10;\nlimit += 1;";
+        let diagnostics = parse_errors(source);
+
+        assert_eq!(
+            diagnostics[0].render("main.mk", source, false),
+            "\
+error: Cannot modify constant limit declared at 1:11
+ --> main.mk:2:1
+  |
+1 | let const limit = 10;
+  |           ----- declared as a constant here
+2 | limit += 1;
+  | ^^^^^ cannot modify a constant
+  |
+  = help: declare it with `let` instead of `let const` if it needs to change
+"
+        );
+
+        // Markers on one line are drawn left to right
+        let source = "let const h = {}; h[\"k\"] = 1";
+        let diagnostics = parse_errors(source);
+
+        assert_eq!(
+            diagnostics[0].render("main.mk", source, false),
+            "\
+error: Cannot modify constant h declared at 1:11
+ --> main.mk:1:19
+  |
+1 | let const h = {}; h[\"k\"] = 1
+  |           - declared as a constant here
+  |                   ^ cannot modify a constant
+  |
+  = help: declare it with `let` instead of `let const` if it needs to change
+"
+        );
+    }
+    #[test]
+    fn it_colours_output_on_request() {
+        let source = "break";
+        let diagnostics = parse_errors(source);
+
+        let plain = diagnostics[0].render("main.mk", source, false);
+        let coloured = diagnostics[0].render("main.mk", source, true);
+        assert!(!plain.contains('\x1b'));
+        assert!(coloured.starts_with("\x1b[1;31merror\x1b[0m"));
+        assert!(coloured.contains("\x1b[1;31m^^^^^ not inside a loop\x1b[0m"));
+    }
+    #[test]
+    fn it_explains_loop_control_in_functions() {
+        let source = "break";
+        let diagnostics = parse_errors(source);
+        assert!(diagnostics[0].help.is_empty());
+
+        let source = "while x { let f = fn() { break } }";
+        let diagnostics = parse_errors(source);
+        assert_eq!(
+            diagnostics[0].help,
+            vec!["loops do not reach into the bodies of functions defined in them"]
+        );
+    }
+}
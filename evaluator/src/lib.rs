@@ -0,0 +1,1566 @@
+#![allow(clippy::needless_return)]
+
+use ast as AST;
+use std::{cell::RefCell, collections::HashMap, fmt, rc::Rc};
+use tokens::Span;
+
+// Returns early with any `Object::Error`, passing every other object through
+macro_rules! try_eval {
+    ($object:expr) => {
+        match $object {
+            error @ Object::Error(_) => return error,
+            object => object,
+        }
+    };
+}
+
+pub type BuiltinFn = fn(Vec<Object>) -> Object;
+
+/// A function implemented by the interpreter itself, like `len`.
+#[derive(Debug, Clone, Copy)]
+pub struct Builtin {
+    pub name: &'static str,
+    pub func: BuiltinFn,
+}
+
+/// A function value: its literal plus the scope it was defined in, which
+/// every call runs inside of.
+pub struct Function {
+    pub literal: Rc<AST::FunctionLiteral>,
+    pub env: Env,
+}
+
+// The captured scope can hold the function itself, so it isn't printed
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Function")
+            .field("literal", &self.literal)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The objects that can key a hash. Floats are left out since they can't
+/// be compared for equality reliably.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum HashKey {
+    Integer(i64),
+    Boolean(bool),
+    String(String),
+}
+
+impl From<HashKey> for Object {
+    fn from(key: HashKey) -> Object {
+        return match key {
+            HashKey::Integer(value) => Object::Integer(value),
+            HashKey::Boolean(value) => Object::Boolean(value),
+            HashKey::String(value) => Object::String(value),
+        };
+    }
+}
+
+impl fmt::Display for HashKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HashKey::Integer(value) => write!(f, "{}", value),
+            HashKey::Boolean(value) => write!(f, "{}", value),
+            HashKey::String(value) => write!(f, "{:?}", value),
+        }
+    }
+}
+
+/// The contents of a hash, kept in insertion order.
+#[derive(Debug, Clone, Default)]
+pub struct HashPairs {
+    pairs: Vec<(HashKey, Object)>,
+    // Position of each key in `pairs`
+    index: HashMap<HashKey, usize>,
+}
+
+impl HashPairs {
+    pub fn new() -> HashPairs {
+        return HashPairs::default();
+    }
+
+    pub fn len(&self) -> usize {
+        return self.pairs.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.pairs.is_empty();
+    }
+
+    pub fn get(&self, key: &HashKey) -> Option<&Object> {
+        let position = *self.index.get(key)?;
+        return Some(&self.pairs[position].1);
+    }
+
+    /// Sets the value for `key`. A key that is already present keeps its
+    /// position.
+    pub fn insert(&mut self, key: HashKey, value: Object) {
+        match self.index.get(&key) {
+            Some(&position) => self.pairs[position].1 = value,
+            None => {
+                self.index.insert(key.clone(), self.pairs.len());
+                self.pairs.push((key, value));
+            }
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(HashKey, Object)> {
+        return self.pairs.iter();
+    }
+
+    fn equals(&self, other: &Self, visiting: &mut Visiting) -> bool {
+        return self.len() == other.len()
+            && self.iter().all(|(key, value)| {
+                other
+                    .get(key)
+                    .is_some_and(|other| value.equals(other, visiting))
+            });
+    }
+}
+
+// Equal if they hold the same pairs, whatever order they were added in
+impl PartialEq for HashPairs {
+    fn eq(&self, other: &Self) -> bool {
+        return self.equals(other, &mut Vec::new());
+    }
+}
+
+// The pairs of containers being compared, so one that holds itself doesn't
+// recurse forever. A pair met again is taken to be equal, as any difference
+// will be found by the comparison already under way.
+type Visiting = Vec<(*const (), *const ())>;
+
+/// A runtime value. Arrays and hashes are shared by reference, so changing
+/// one through any binding is visible through all of them.
+#[derive(Debug, Clone)]
+pub enum Object {
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    String(String),
+    Null,
+    Array(Rc<RefCell<Vec<Object>>>),
+    Hash(Rc<RefCell<HashPairs>>),
+    Function(Rc<Function>),
+    Builtin(Builtin),
+    // Unwinds to the nearest function call, carrying the value returned
+    ReturnValue(Box<Object>),
+    // Unwind to the nearest loop
+    Break,
+    Continue,
+    Error(String),
+}
+
+impl Object {
+    pub fn type_name(&self) -> &'static str {
+        return match self {
+            Object::Integer(_) => "INTEGER",
+            Object::Float(_) => "FLOAT",
+            Object::Boolean(_) => "BOOLEAN",
+            Object::String(_) => "STRING",
+            Object::Null => "NULL",
+            Object::Array(_) => "ARRAY",
+            Object::Hash(_) => "HASH",
+            Object::Function(_) => "FUNCTION",
+            Object::Builtin(_) => "BUILTIN",
+            Object::ReturnValue(_) => "RETURN_VALUE",
+            Object::Break => "BREAK",
+            Object::Continue => "CONTINUE",
+            Object::Error(_) => "ERROR",
+        };
+    }
+
+    /// The key this object is stored under in a hash, if it can be one.
+    pub fn hash_key(&self) -> Option<HashKey> {
+        return match self {
+            Object::Integer(value) => Some(HashKey::Integer(*value)),
+            Object::Boolean(value) => Some(HashKey::Boolean(*value)),
+            Object::String(value) => Some(HashKey::String(value.clone())),
+            _ => None,
+        };
+    }
+
+    pub fn is_error(&self) -> bool {
+        return matches!(self, Object::Error(_));
+    }
+
+    /// Only `false` and `null` are falsy.
+    pub fn is_truthy(&self) -> bool {
+        return !matches!(self, Object::Boolean(false) | Object::Null);
+    }
+
+    fn equals(&self, other: &Self, visiting: &mut Visiting) -> bool {
+        return match (self, other) {
+            (Object::Integer(left), Object::Integer(right)) => left == right,
+            (Object::Float(left), Object::Float(right)) => left == right,
+            (Object::Boolean(left), Object::Boolean(right)) => left == right,
+            (Object::String(left), Object::String(right)) => left == right,
+            (Object::Null, Object::Null) => true,
+            (Object::Array(left), Object::Array(right)) => {
+                compare(left, right, visiting, |left, right, visiting| {
+                    left.len() == right.len()
+                        && left
+                            .iter()
+                            .zip(right.iter())
+                            .all(|(left, right)| left.equals(right, visiting))
+                })
+            }
+            (Object::Hash(left), Object::Hash(right)) => {
+                compare(left, right, visiting, |left, right, visiting| {
+                    left.equals(right, visiting)
+                })
+            }
+            (Object::Function(left), Object::Function(right)) => Rc::ptr_eq(left, right),
+            (Object::Builtin(left), Object::Builtin(right)) => left.name == right.name,
+            (Object::ReturnValue(left), Object::ReturnValue(right)) => left.equals(right, visiting),
+            (Object::Break, Object::Break) => true,
+            (Object::Continue, Object::Continue) => true,
+            (Object::Error(left), Object::Error(right)) => left == right,
+            _ => false,
+        };
+    }
+
+    // Writes the object, quoting strings inside containers so they stand
+    // out. A container already being written prints as `[...]` or `{...}`.
+    fn write(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+        visiting: &mut Vec<*const ()>,
+        nested: bool,
+    ) -> fmt::Result {
+        match self {
+            Object::String(value) if nested => write!(f, "{:?}", value),
+            Object::Array(elements) => {
+                let pointer = Rc::as_ptr(elements) as *const ();
+                if visiting.contains(&pointer) {
+                    return write!(f, "[...]");
+                }
+
+                visiting.push(pointer);
+                write!(f, "[")?;
+                for (i, element) in elements.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    element.write(f, visiting, true)?;
+                }
+                visiting.pop();
+                write!(f, "]")
+            }
+            Object::Hash(pairs) => {
+                let pointer = Rc::as_ptr(pairs) as *const ();
+                if visiting.contains(&pointer) {
+                    return write!(f, "{{...}}");
+                }
+
+                visiting.push(pointer);
+                write!(f, "{{")?;
+                for (i, (key, value)) in pairs.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: ", key)?;
+                    value.write(f, visiting, true)?;
+                }
+                visiting.pop();
+                write!(f, "}}")
+            }
+            Object::ReturnValue(value) => value.write(f, visiting, nested),
+            object => write!(f, "{}", object),
+        }
+    }
+}
+
+// Compares two shared containers, which are equal if they are the same one
+fn compare<T>(
+    left: &Rc<RefCell<T>>,
+    right: &Rc<RefCell<T>>,
+    visiting: &mut Visiting,
+    contents_equal: impl FnOnce(&T, &T, &mut Visiting) -> bool,
+) -> bool {
+    if Rc::ptr_eq(left, right) {
+        return true;
+    }
+
+    let pair = (
+        Rc::as_ptr(left) as *const (),
+        Rc::as_ptr(right) as *const (),
+    );
+    if visiting.contains(&pair) {
+        return true;
+    }
+
+    visiting.push(pair);
+    let equal = contents_equal(&left.borrow(), &right.borrow(), visiting);
+    visiting.pop();
+
+    return equal;
+}
+
+impl PartialEq for Object {
+    fn eq(&self, other: &Self) -> bool {
+        return self.equals(other, &mut Vec::new());
+    }
+}
+
+impl fmt::Display for Object {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Object::Integer(value) => write!(f, "{}", value),
+            Object::Float(value) => write!(f, "{:?}", value),
+            Object::Boolean(value) => write!(f, "{}", value),
+            Object::String(value) => write!(f, "{}", value),
+            Object::Null => write!(f, "null"),
+            Object::Array(_) | Object::Hash(_) | Object::ReturnValue(_) => {
+                self.write(f, &mut Vec::new(), false)
+            }
+            Object::Function(function) => write!(f, "{}", function.literal),
+            Object::Builtin(builtin) => write!(f, "builtin function {}", builtin.name),
+            Object::Break => write!(f, "break"),
+            Object::Continue => write!(f, "continue"),
+            Object::Error(message) => write!(f, "ERROR: {}", message),
+        }
+    }
+}
+
+/// A shared handle to a scope, so functions and loops can enclose it.
+pub type Env = Rc<RefCell<Environment>>;
+
+#[derive(Debug)]
+struct Binding {
+    value: Object,
+    // Where the name was declared with `let const`, if it was
+    constant: Option<Span>,
+}
+
+/// Why `Environment::assign` refused to update a name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssignError {
+    Undefined,
+    // Carries where the constant was declared
+    Constant(Span),
+}
+
+/// The bindings of one scope, falling back to the scope that encloses it.
+#[derive(Debug, Default)]
+pub struct Environment {
+    store: HashMap<String, Binding>,
+    outer: Option<Env>,
+}
+
+impl Environment {
+    pub fn new() -> Env {
+        return Rc::new(RefCell::new(Environment::default()));
+    }
+
+    pub fn new_enclosed(outer: &Env) -> Env {
+        return Rc::new(RefCell::new(Environment {
+            store: HashMap::new(),
+            outer: Some(Rc::clone(outer)),
+        }));
+    }
+
+    pub fn get(&self, name: &str) -> Option<Object> {
+        return match self.store.get(name) {
+            Some(binding) => Some(binding.value.clone()),
+            None => self.outer.as_ref()?.borrow().get(name),
+        };
+    }
+
+    /// Declares `name` in this scope, shadowing any outer binding.
+    pub fn set(&mut self, name: impl Into<String>, value: Object) {
+        self.store.insert(
+            name.into(),
+            Binding {
+                value,
+                constant: None,
+            },
+        );
+    }
+
+    /// Declares `name` in this scope as a constant declared at `declared`.
+    pub fn set_const(&mut self, name: impl Into<String>, value: Object, declared: Span) {
+        self.store.insert(
+            name.into(),
+            Binding {
+                value,
+                constant: Some(declared),
+            },
+        );
+    }
+
+    /// Where `name` was declared, if it resolves to a constant.
+    pub fn constant(&self, name: &str) -> Option<Span> {
+        return match self.store.get(name) {
+            Some(binding) => binding.constant,
+            None => self.outer.as_ref()?.borrow().constant(name),
+        };
+    }
+
+    /// Where `name` was declared, if this scope itself declares it as a
+    /// constant.
+    pub fn local_constant(&self, name: &str) -> Option<Span> {
+        return self.store.get(name)?.constant;
+    }
+
+    /// Updates `name` in the innermost scope that declares it, unless that
+    /// binding is a constant.
+    pub fn assign(&mut self, name: &str, value: Object) -> Result<(), AssignError> {
+        if let Some(binding) = self.store.get_mut(name) {
+            if let Some(declared) = binding.constant {
+                return Err(AssignError::Constant(declared));
+            }
+
+            binding.value = value;
+            return Ok(());
+        }
+
+        return match &self.outer {
+            Some(outer) => outer.borrow_mut().assign(name, value),
+            None => Err(AssignError::Undefined),
+        };
+    }
+}
+
+fn error(message: String) -> Object {
+    return Object::Error(message);
+}
+
+fn constant_error(action: &str, name: &str, declared: Span) -> Object {
+    return error(format!(
+        "cannot {} constant `{}` declared at {}",
+        action, name, declared
+    ));
+}
+
+/// Runs a program, returning the value of its last statement or the first
+/// error it runs into.
+pub fn eval(program: &AST::Program, env: &Env) -> Object {
+    let mut result = Object::Null;
+
+    for statement in &program.statements {
+        result = eval_statement(statement, env);
+
+        match result {
+            Object::ReturnValue(value) => return *value,
+            Object::Error(_) => return result,
+            _ => {}
+        }
+    }
+
+    return result;
+}
+
+fn eval_block_statement(block: &AST::BlockStatement, env: &Env) -> Object {
+    let mut result = Object::Null;
+
+    for statement in &block.statements {
+        result = eval_statement(statement, env);
+
+        if matches!(
+            result,
+            Object::ReturnValue(_) | Object::Error(_) | Object::Break | Object::Continue
+        ) {
+            return result;
+        }
+    }
+
+    return result;
+}
+
+fn eval_statement(statement: &AST::Statement, env: &Env) -> Object {
+    return match statement {
+        AST::Statement::Let(statement) => {
+            let value = try_eval!(eval_expression(&statement.value, env));
+            let name = &statement.name;
+
+            if let Some(declared) = env.borrow().local_constant(&name.value) {
+                return constant_error("redeclare", &name.value, declared);
+            }
+
+            match statement.constant {
+                true => env
+                    .borrow_mut()
+                    .set_const(name.value.as_str(), value, name.token.span),
+                false => env.borrow_mut().set(name.value.as_str(), value),
+            }
+            Object::Null
+        }
+        AST::Statement::Return(statement) => {
+            let value = try_eval!(eval_expression(&statement.return_value, env));
+            Object::ReturnValue(Box::new(value))
+        }
+        AST::Statement::Expression(statement) => eval_expression(&statement.expression, env),
+        AST::Statement::Assignment(statement) => {
+            let value = try_eval!(eval_expression(&statement.value, env));
+            write_place(&Place::Variable(statement.name.value.clone()), value, env)
+        }
+        AST::Statement::CompoundAssignment(statement) => {
+            let place = match eval_place(&statement.target, env) {
+                Ok(place) => place,
+                Err(error) => return error,
+            };
+            let current = try_eval!(read_place(&place, env));
+            let value = try_eval!(eval_expression(&statement.value, env));
+            let result = try_eval!(eval_infix(&statement.operator, current, value));
+            write_place(&place, result, env)
+        }
+        AST::Statement::IndexAssignment(statement) => {
+            let container = try_eval!(eval_expression(&statement.array, env));
+            let index = try_eval!(eval_expression(&statement.index, env));
+            let value = try_eval!(eval_expression(&statement.value, env));
+            let place = Place::Element {
+                container,
+                index,
+                root: statement
+                    .array
+                    .root_identifier()
+                    .map(|name| name.value.clone()),
+            };
+            write_place(&place, value, env)
+        }
+        AST::Statement::For(statement) => eval_for_statement(statement, env),
+        AST::Statement::ForIn(statement) => eval_for_in_statement(statement, env),
+        AST::Statement::While(statement) => eval_while_statement(statement, env),
+        AST::Statement::Break(_) => Object::Break,
+        AST::Statement::Continue(_) => Object::Continue,
+    };
+}
+
+fn eval_expression(expression: &AST::Expression, env: &Env) -> Object {
+    return match expression {
+        AST::Expression::Identifier(identifier) => eval_identifier(identifier, env),
+        AST::Expression::Integer(integer) => Object::Integer(integer.value),
+        AST::Expression::Float(float) => Object::Float(float.value),
+        AST::Expression::Boolean(boolean) => Object::Boolean(boolean.value),
+        AST::Expression::String(string) => Object::String(string.value.clone()),
+        AST::Expression::Prefix(prefix) => {
+            let right = try_eval!(eval_expression(&prefix.right, env));
+            eval_prefix(&prefix.operator, right)
+        }
+        AST::Expression::Infix(infix) => eval_infix_expression(infix, env),
+        AST::Expression::Update(update) => eval_update_expression(update, env),
+        AST::Expression::If(if_) => {
+            let condition = try_eval!(eval_expression(&if_.condition, env));
+
+            if condition.is_truthy() {
+                eval_block_statement(&if_.consequence, env)
+            } else if let Some(alternative) = &if_.alternative {
+                eval_block_statement(alternative, env)
+            } else {
+                Object::Null
+            }
+        }
+        AST::Expression::Function(function) => Object::Function(Rc::new(Function {
+            literal: Rc::clone(function),
+            env: Rc::clone(env),
+        })),
+        AST::Expression::Call(call) => {
+            let function = try_eval!(eval_expression(&call.function, env));
+
+            let mut arguments = Vec::new();
+            for argument in call.arguments.iter().flatten() {
+                arguments.push(try_eval!(eval_expression(argument, env)));
+            }
+
+            apply_function(function, arguments)
+        }
+        AST::Expression::Array(array) => {
+            let mut elements = Vec::with_capacity(array.elements.len());
+            for element in &array.elements {
+                elements.push(try_eval!(eval_expression(element, env)));
+            }
+
+            Object::Array(Rc::new(RefCell::new(elements)))
+        }
+        AST::Expression::Hash(hash) => eval_hash_literal(hash, env),
+        AST::Expression::Index(index) => {
+            let left = try_eval!(eval_expression(&index.left, env));
+            let index = try_eval!(eval_expression(&index.index, env));
+            eval_index(&left, &index)
+        }
+    };
+}
+
+fn eval_identifier(identifier: &AST::Identifier, env: &Env) -> Object {
+    if let Some(value) = env.borrow().get(&identifier.value) {
+        return value;
+    }
+
+    return match lookup_builtin(&identifier.value) {
+        Some(builtin) => Object::Builtin(builtin),
+        None => error(format!("identifier not found: {}", identifier.value)),
+    };
+}
+
+fn eval_prefix(operator: &str, right: Object) -> Object {
+    return match (operator, &right) {
+        ("!", _) => Object::Boolean(!right.is_truthy()),
+        ("-", Object::Integer(value)) => match value.checked_neg() {
+            Some(value) => Object::Integer(value),
+            None => error(format!("integer overflow: -{}", value)),
+        },
+        ("-", Object::Float(value)) => Object::Float(-value),
+        ("~", Object::Integer(value)) => Object::Integer(!value),
+        _ => error(format!(
+            "unknown operator: {}{}",
+            operator,
+            right.type_name()
+        )),
+    };
+}
+
+fn eval_infix_expression(infix: &AST::InfixExpression, env: &Env) -> Object {
+    let left = try_eval!(eval_expression(&infix.left, env));
+
+    // The right side only runs if the left one didn't settle the answer
+    match infix.operator.as_str() {
+        "&&" if !left.is_truthy() => return Object::Boolean(false),
+        "||" if left.is_truthy() => return Object::Boolean(true),
+        "&&" | "||" => {
+            let right = try_eval!(eval_expression(&infix.right, env));
+            return Object::Boolean(right.is_truthy());
+        }
+        _ => {}
+    }
+
+    let right = try_eval!(eval_expression(&infix.right, env));
+    return eval_infix(&infix.operator, left, right);
+}
+
+fn eval_infix(operator: &str, left: Object, right: Object) -> Object {
+    let result = match (&left, &right) {
+        (Object::Integer(l), Object::Integer(r)) => eval_integer_infix(operator, *l, *r),
+        (Object::Integer(l), Object::Float(r)) => eval_float_infix(operator, *l as f64, *r),
+        (Object::Float(l), Object::Integer(r)) => eval_float_infix(operator, *l, *r as f64),
+        (Object::Float(l), Object::Float(r)) => eval_float_infix(operator, *l, *r),
+        (Object::String(l), Object::String(r)) => eval_string_infix(operator, l, r),
+        // Values of different types are never equal, which is how a missing
+        // element or key (null) can be checked for
+        _ if left.type_name() != right.type_name() && matches!(operator, "==" | "!=") => {
+            Some(Object::Boolean(operator == "!="))
+        }
+        _ if left.type_name() != right.type_name() => {
+            return error(format!(
+                "type mismatch: {} {} {}",
+                left.type_name(),
+                operator,
+                right.type_name()
+            ));
+        }
+        _ => match operator {
+            "==" => Some(Object::Boolean(left == right)),
+            "!=" => Some(Object::Boolean(left != right)),
+            _ => None,
+        },
+    };
+
+    return result.unwrap_or_else(|| {
+        error(format!(
+            "unknown operator: {} {} {}",
+            left.type_name(),
+            operator,
+            right.type_name()
+        ))
+    });
+}
+
+// Returns None for operators integers don't support
+fn eval_integer_infix(operator: &str, left: i64, right: i64) -> Option<Object> {
+    let result = match operator {
+        "+" => left.checked_add(right),
+        "-" => left.checked_sub(right),
+        "*" => left.checked_mul(right),
+        "/" | "%" if right == 0 => return Some(error("division by zero".to_string())),
+        "/" => left.checked_div(right),
+        "%" => left.checked_rem(right),
+        "**" if right < 0 => {
+            return Some(error(format!(
+                "negative integer exponent: {} ** {}",
+                left, right
+            )));
+        }
+        "**" => u32::try_from(right)
+            .ok()
+            .and_then(|exponent| left.checked_pow(exponent)),
+        "&" => Some(left & right),
+        "|" => Some(left | right),
+        "^" => Some(left ^ right),
+        "<<" | ">>" if !(0..64).contains(&right) => {
+            return Some(error(format!("shift amount out of range: {}", right)));
+        }
+        "<<" => Some(left << right),
+        ">>" => Some(left >> right),
+        "<" => return Some(Object::Boolean(left < right)),
+        ">" => return Some(Object::Boolean(left > right)),
+        "<=" => return Some(Object::Boolean(left <= right)),
+        ">=" => return Some(Object::Boolean(left >= right)),
+        "==" => return Some(Object::Boolean(left == right)),
+        "!=" => return Some(Object::Boolean(left != right)),
+        _ => return None,
+    };
+
+    return Some(match result {
+        Some(value) => Object::Integer(value),
+        None => error(format!("integer overflow: {} {} {}", left, operator, right)),
+    });
+}
+
+fn eval_float_infix(operator: &str, left: f64, right: f64) -> Option<Object> {
+    return Some(match operator {
+        "+" => Object::Float(left + right),
+        "-" => Object::Float(left - right),
+        "*" => Object::Float(left * right),
+        "/" => Object::Float(left / right),
+        "%" => Object::Float(left % right),
+        "**" => Object::Float(left.powf(right)),
+        "<" => Object::Boolean(left < right),
+        ">" => Object::Boolean(left > right),
+        "<=" => Object::Boolean(left <= right),
+        ">=" => Object::Boolean(left >= right),
+        "==" => Object::Boolean(left == right),
+        "!=" => Object::Boolean(left != right),
+        _ => return None,
+    });
+}
+
+fn eval_string_infix(operator: &str, left: &str, right: &str) -> Option<Object> {
+    return Some(match operator {
+        "+" => Object::String(format!("{}{}", left, right)),
+        "<" => Object::Boolean(left < right),
+        ">" => Object::Boolean(left > right),
+        "<=" => Object::Boolean(left <= right),
+        ">=" => Object::Boolean(left >= right),
+        "==" => Object::Boolean(left == right),
+        "!=" => Object::Boolean(left != right),
+        _ => return None,
+    });
+}
+
+// Something that can be assigned to: a variable or an element of an array
+// or hash. Elements remember the variable they were reached through, so a
+// constant's contents can't be changed either.
+enum Place {
+    Variable(String),
+    Element {
+        container: Object,
+        index: Object,
+        root: Option<String>,
+    },
+}
+
+fn eval_place(target: &AST::Expression, env: &Env) -> Result<Place, Object> {
+    return match target {
+        AST::Expression::Identifier(identifier) => Ok(Place::Variable(identifier.value.clone())),
+        AST::Expression::Index(index_expression) => {
+            let container = eval_expression(&index_expression.left, env);
+            if container.is_error() {
+                return Err(container);
+            }
+
+            let index = eval_expression(&index_expression.index, env);
+            if index.is_error() {
+                return Err(index);
+            }
+
+            Ok(Place::Element {
+                container,
+                index,
+                root: target.root_identifier().map(|name| name.value.clone()),
+            })
+        }
+        // The parser only produces identifier and index targets
+        _ => Err(error(format!("cannot assign to {}", target))),
+    };
+}
+
+fn read_place(place: &Place, env: &Env) -> Object {
+    return match place {
+        Place::Variable(name) => match env.borrow().get(name) {
+            Some(value) => value,
+            None => error(format!("identifier not found: {}", name)),
+        },
+        Place::Element {
+            container, index, ..
+        } => eval_index(container, index),
+    };
+}
+
+// Stores `value` at `place`, returning null or an error
+fn write_place(place: &Place, value: Object, env: &Env) -> Object {
+    if let Place::Element {
+        root: Some(name), ..
+    } = place
+    {
+        if let Some(declared) = env.borrow().constant(name) {
+            return constant_error("modify", name, declared);
+        }
+    }
+
+    return match place {
+        Place::Variable(name) => match env.borrow_mut().assign(name, value) {
+            Ok(()) => Object::Null,
+            Err(AssignError::Undefined) => error(format!("identifier not found: {}", name)),
+            Err(AssignError::Constant(declared)) => constant_error("modify", name, declared),
+        },
+        Place::Element {
+            container: Object::Array(elements),
+            index: Object::Integer(index),
+            ..
+        } => {
+            let mut elements = elements.borrow_mut();
+            let length = elements.len();
+
+            match usize::try_from(*index)
+                .ok()
+                .and_then(|index| elements.get_mut(index))
+            {
+                Some(slot) => {
+                    *slot = value;
+                    Object::Null
+                }
+                None => error(format!("index out of range: {} (length {})", index, length)),
+            }
+        }
+        Place::Element {
+            container: Object::Hash(pairs),
+            index: key,
+            ..
+        } => match key.hash_key() {
+            Some(key) => {
+                pairs.borrow_mut().insert(key, value);
+                Object::Null
+            }
+            None => unusable_hash_key(key),
+        },
+        Place::Element {
+            container, index, ..
+        } => error(format!(
+            "index assignment not supported: {}[{}]",
+            container.type_name(),
+            index.type_name()
+        )),
+    };
+}
+
+fn eval_update_expression(update: &AST::UpdateExpression, env: &Env) -> Object {
+    let place = match eval_place(&update.target, env) {
+        Ok(place) => place,
+        Err(error) => return error,
+    };
+    let current = try_eval!(read_place(&place, env));
+
+    if !matches!(current, Object::Integer(_) | Object::Float(_)) {
+        return error(format!(
+            "unknown operator: {}{}",
+            update.operator,
+            current.type_name()
+        ));
+    }
+
+    let operator = if update.operator == "++" { "+" } else { "-" };
+    let new = try_eval!(eval_infix(operator, current.clone(), Object::Integer(1)));
+    try_eval!(write_place(&place, new.clone(), env));
+
+    return if update.prefix { new } else { current };
+}
+
+fn unusable_hash_key(key: &Object) -> Object {
+    return error(format!("unusable as hash key: {}", key.type_name()));
+}
+
+fn eval_hash_literal(hash: &AST::HashLiteral, env: &Env) -> Object {
+    let mut pairs = HashPairs::new();
+
+    for (key, value) in &hash.pairs {
+        let key = try_eval!(eval_expression(key, env));
+        let Some(key) = key.hash_key() else {
+            return unusable_hash_key(&key);
+        };
+        let value = try_eval!(eval_expression(value, env));
+
+        // A repeated key keeps its first position but takes the last value
+        pairs.insert(key, value);
+    }
+
+    return Object::Hash(Rc::new(RefCell::new(pairs)));
+}
+
+fn eval_index(left: &Object, index: &Object) -> Object {
+    return match (left, index) {
+        (Object::Array(elements), Object::Integer(index)) => usize::try_from(*index)
+            .ok()
+            .and_then(|index| elements.borrow().get(index).cloned())
+            .unwrap_or(Object::Null),
+        (Object::Hash(pairs), _) => match index.hash_key() {
+            Some(key) => pairs.borrow().get(&key).cloned().unwrap_or(Object::Null),
+            None => unusable_hash_key(index),
+        },
+        _ => error(format!(
+            "index operator not supported: {}[{}]",
+            left.type_name(),
+            index.type_name()
+        )),
+    };
+}
+
+fn apply_function(function: Object, arguments: Vec<Object>) -> Object {
+    return match function {
+        Object::Function(function) => {
+            if function.literal.parameters.len() != arguments.len() {
+                return error(format!(
+                    "wrong number of arguments: want={}, got={}",
+                    function.literal.parameters.len(),
+                    arguments.len()
+                ));
+            }
+
+            // Calls run in the scope the function was defined in, not the caller's
+            let call_env = Environment::new_enclosed(&function.env);
+            for (parameter, argument) in function.literal.parameters.iter().zip(arguments) {
+                call_env
+                    .borrow_mut()
+                    .set(parameter.value.as_str(), argument);
+            }
+
+            match eval_block_statement(&function.literal.body, &call_env) {
+                Object::ReturnValue(value) => *value,
+                result => result,
+            }
+        }
+        Object::Builtin(builtin) => (builtin.func)(arguments),
+        other => error(format!("not a function: {}", other.type_name())),
+    };
+}
+
+// Runs a loop body, returning Some if the loop has to stop: None to carry on,
+// Some(Null) after `break`, and the object itself for returns and errors
+fn eval_loop_body(body: &AST::BlockStatement, env: &Env) -> Option<Object> {
+    return match eval_block_statement(body, env) {
+        Object::Break => Some(Object::Null),
+        result @ (Object::ReturnValue(_) | Object::Error(_)) => Some(result),
+        _ => None,
+    };
+}
+
+fn eval_while_statement(statement: &AST::WhileStatement, env: &Env) -> Object {
+    loop {
+        let condition = try_eval!(eval_expression(&statement.condition, env));
+        if !condition.is_truthy() {
+            return Object::Null;
+        }
+
+        // Each iteration gets its own scope, as for `for (x in xs)`
+        let body_env = Environment::new_enclosed(env);
+        if let Some(result) = eval_loop_body(&statement.body, &body_env) {
+            return result;
+        }
+    }
+}
+
+fn eval_for_statement(statement: &AST::ForStatement, env: &Env) -> Object {
+    // Keeps the loop variable out of the surrounding scope
+    let loop_env = Environment::new_enclosed(env);
+
+    if let Some(init) = &statement.init {
+        try_eval!(eval_statement(init, &loop_env));
+    }
+
+    loop {
+        if let Some(condition) = &statement.condition {
+            let condition = try_eval!(eval_expression(condition, &loop_env));
+            if !condition.is_truthy() {
+                return Object::Null;
+            }
+        }
+
+        let body_env = Environment::new_enclosed(&loop_env);
+        if let Some(result) = eval_loop_body(&statement.body, &body_env) {
+            return result;
+        }
+
+        if let Some(update) = &statement.update {
+            try_eval!(eval_statement(update, &loop_env));
+        }
+    }
+}
+
+fn eval_for_in_statement(statement: &AST::ForInStatement, env: &Env) -> Object {
+    let iterable = try_eval!(eval_expression(&statement.iterable, env));
+
+    // Iterates over a snapshot, so the body can change the original freely
+    let entries: Vec<(Object, Object)> = match &iterable {
+        Object::Array(elements) => elements
+            .borrow()
+            .iter()
+            .enumerate()
+            .map(|(index, element)| (Object::Integer(index as i64), element.clone()))
+            .collect(),
+        Object::Hash(pairs) => pairs
+            .borrow()
+            .iter()
+            .map(|(key, value)| (Object::from(key.clone()), value.clone()))
+            .collect(),
+        Object::String(string) => string
+            .chars()
+            .enumerate()
+            .map(|(index, ch)| {
+                (
+                    Object::Integer(index as i64),
+                    Object::String(ch.to_string()),
+                )
+            })
+            .collect(),
+        _ => return error(format!("cannot iterate over {}", iterable.type_name())),
+    };
+
+    for (key, value) in entries {
+        let loop_env = Environment::new_enclosed(env);
+
+        match &statement.key {
+            Some(name) => {
+                loop_env.borrow_mut().set(name.value.as_str(), key);
+                loop_env
+                    .borrow_mut()
+                    .set(statement.value.value.as_str(), value);
+            }
+            // A single name over a hash takes its keys
+            None if matches!(iterable, Object::Hash(_)) => {
+                loop_env
+                    .borrow_mut()
+                    .set(statement.value.value.as_str(), key);
+            }
+            None => {
+                loop_env
+                    .borrow_mut()
+                    .set(statement.value.value.as_str(), value);
+            }
+        }
+
+        let body_env = Environment::new_enclosed(&loop_env);
+        if let Some(result) = eval_loop_body(&statement.body, &body_env) {
+            return result;
+        }
+    }
+
+    return Object::Null;
+}
+
+// Builtins
+const BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "len",
+        func: builtin_len,
+    },
+    Builtin {
+        name: "first",
+        func: builtin_first,
+    },
+    Builtin {
+        name: "last",
+        func: builtin_last,
+    },
+    Builtin {
+        name: "rest",
+        func: builtin_rest,
+    },
+    Builtin {
+        name: "push",
+        func: builtin_push,
+    },
+    Builtin {
+        name: "puts",
+        func: builtin_puts,
+    },
+];
+
+fn lookup_builtin(name: &str) -> Option<Builtin> {
+    return BUILTINS
+        .iter()
+        .find(|builtin| builtin.name == name)
+        .copied();
+}
+
+fn wrong_arguments(name: &str, want: usize, got: usize) -> Object {
+    return error(format!(
+        "wrong number of arguments to `{}`: want={}, got={}",
+        name, want, got
+    ));
+}
+
+fn unsupported_argument(name: &str, argument: &Object) -> Object {
+    return error(format!(
+        "argument to `{}` not supported, got {}",
+        name,
+        argument.type_name()
+    ));
+}
+
+fn builtin_len(arguments: Vec<Object>) -> Object {
+    if arguments.len() != 1 {
+        return wrong_arguments("len", 1, arguments.len());
+    }
+
+    return match &arguments[0] {
+        Object::String(string) => Object::Integer(string.chars().count() as i64),
+        Object::Array(elements) => Object::Integer(elements.borrow().len() as i64),
+        Object::Hash(pairs) => Object::Integer(pairs.borrow().len() as i64),
+        other => unsupported_argument("len", other),
+    };
+}
+
+fn builtin_first(arguments: Vec<Object>) -> Object {
+    if arguments.len() != 1 {
+        return wrong_arguments("first", 1, arguments.len());
+    }
+
+    return match &arguments[0] {
+        Object::Array(elements) => elements.borrow().first().cloned().unwrap_or(Object::Null),
+        other => unsupported_argument("first", other),
+    };
+}
+
+fn builtin_last(arguments: Vec<Object>) -> Object {
+    if arguments.len() != 1 {
+        return wrong_arguments("last", 1, arguments.len());
+    }
+
+    return match &arguments[0] {
+        Object::Array(elements) => elements.borrow().last().cloned().unwrap_or(Object::Null),
+        other => unsupported_argument("last", other),
+    };
+}
+
+fn builtin_rest(arguments: Vec<Object>) -> Object {
+    if arguments.len() != 1 {
+        return wrong_arguments("rest", 1, arguments.len());
+    }
+
+    return match &arguments[0] {
+        Object::Array(elements) => match elements.borrow().split_first() {
+            Some((_, rest)) => Object::Array(Rc::new(RefCell::new(rest.to_vec()))),
+            None => Object::Null,
+        },
+        other => unsupported_argument("rest", other),
+    };
+}
+
+// Appends to the array in place and returns it
+fn builtin_push(arguments: Vec<Object>) -> Object {
+    if arguments.len() != 2 {
+        return wrong_arguments("push", 2, arguments.len());
+    }
+
+    return match &arguments[0] {
+        Object::Array(elements) => {
+            elements.borrow_mut().push(arguments[1].clone());
+            arguments[0].clone()
+        }
+        other => unsupported_argument("push", other),
+    };
+}
+
+fn builtin_puts(arguments: Vec<Object>) -> Object {
+    for argument in arguments {
+        println!("{}", argument);
+    }
+
+    return Object::Null;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lexer::Lexer;
+    use parser::Parser;
+
+    fn run(input: &str) -> Object {
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse_program();
+        assert!(
+            parser.errors().is_empty(),
+            "parser errors: {:?}",
+            parser.errors()
+        );
+
+        return eval(&program, &Environment::new());
+    }
+
+    fn assert_runs(tests: Vec<(&str, &str)>) {
+        for (input, expected) in tests {
+            assert_eq!(run(input).to_string(), expected, "input: {}", input);
+        }
+    }
+
+    #[test]
+    fn it_evaluates_arithmetic() {
+        assert_runs(vec![
+            ("5", "5"),
+            ("-10 + 2 * 3", "-4"),
+            ("(5 + 10 * 2 + 15 / 3) * 2 + -10", "50"),
+            ("7 % 3 + 2 ** 3 ** 2", "513"),
+            ("0xF0 | 0b1010 ^ 3 & ~0", "249"),
+            ("1 << 4 >> 2", "4"),
+            ("1.5 * 2", "3.0"),
+            ("10 / 4.0", "2.5"),
+            ("2 ** -1.0", "0.5"),
+        ]);
+    }
+    #[test]
+    fn it_evaluates_booleans_and_comparisons() {
+        assert_runs(vec![
+            ("1 < 2 == true", "true"),
+            ("3 >= 3.0", "true"),
+            ("!5", "false"),
+            ("!!true", "true"),
+            ("\"abc\" < \"abd\"", "true"),
+            ("[1, [2]] == [1, [2]]", "true"),
+            ("true != false", "true"),
+            ("[1][5] == 1", "false"),
+            ("{\"a\": 1}[\"b\"] != 1", "true"),
+            ("1 == true", "false"),
+            ("1 != true", "true"),
+            ("\"1\" == 1", "false"),
+        ]);
+    }
+    #[test]
+    fn it_short_circuits_logical_operators() {
+        assert_runs(vec![
+            ("false && missing", "false"),
+            ("true || missing", "true"),
+            ("1 && \"a\"", "true"),
+            ("false || 0 == 1", "false"),
+        ]);
+        assert_eq!(
+            run("true && missing"),
+            Object::Error("identifier not found: missing".to_string())
+        );
+    }
+    #[test]
+    fn it_evaluates_conditionals_and_returns() {
+        assert_runs(vec![
+            ("if (1 < 2) { 10 } else { 20 }", "10"),
+            ("if false { 10 }", "null"),
+            (
+                "if x { 1 } else if true { 2 }",
+                "ERROR: identifier not found: x",
+            ),
+            ("9; return 2 * 5; 9;", "10"),
+            ("if true { if true { return 10; } return 1; }", "10"),
+        ]);
+    }
+    #[test]
+    fn it_reports_runtime_errors() {
+        assert_runs(vec![
+            ("5 + true; 5", "ERROR: type mismatch: INTEGER + BOOLEAN"),
+            ("[1][5] < 1", "ERROR: type mismatch: NULL < INTEGER"),
+            ("-true", "ERROR: unknown operator: -BOOLEAN"),
+            ("\"a\" - \"b\"", "ERROR: unknown operator: STRING - STRING"),
+            ("1.5 & 1", "ERROR: unknown operator: FLOAT & INTEGER"),
+            ("1 / 0", "ERROR: division by zero"),
+            ("2 ** -1", "ERROR: negative integer exponent: 2 ** -1"),
+            (
+                "9223372036854775807 + 1",
+                "ERROR: integer overflow: 9223372036854775807 + 1",
+            ),
+            ("1 << 64", "ERROR: shift amount out of range: 64"),
+            ("5()", "ERROR: not a function: INTEGER"),
+            (
+                "fn(x) { x }()",
+                "ERROR: wrong number of arguments: want=1, got=0",
+            ),
+            ("x = 1", "ERROR: identifier not found: x"),
+            (
+                "1[0]",
+                "ERROR: index operator not supported: INTEGER[INTEGER]",
+            ),
+            ("for (x in 5) {}", "ERROR: cannot iterate over INTEGER"),
+        ]);
+    }
+    #[test]
+    fn it_binds_and_assigns_variables() {
+        assert_runs(vec![
+            ("let a = 5; let b = a * 2; b", "10"),
+            ("let a = 1; a = a + 1; a", "2"),
+            ("let a = 10; a -= 3; a *= 2; a %= 5; a", "4"),
+            ("let s = \"a\"; s += \"b\"; s", "ab"),
+            ("let a = 1; if true { a = 2 }; a", "2"),
+        ]);
+    }
+    #[test]
+    fn it_evaluates_increments_and_decrements() {
+        assert_runs(vec![
+            ("let i = 1; let j = i++; [i, j]", "[2, 1]"),
+            ("let i = 1; let j = ++i; [i, j]", "[2, 2]"),
+            ("let i = 1; [i--, i, --i]", "[1, 0, -1]"),
+            ("let a = [1, 2]; a[1]++; ++a[0]; a", "[2, 3]"),
+            ("let f = 0.5; f++; f", "1.5"),
+            ("let s = \"a\"; s++", "ERROR: unknown operator: ++STRING"),
+        ]);
+    }
+    #[test]
+    fn it_evaluates_arrays_and_hashes() {
+        assert_runs(vec![
+            ("[1, 2 * 2, \"three\"]", "[1, 4, \"three\"]"),
+            ("[1, 2, 3][1]", "2"),
+            ("[1, 2, 3][3]", "null"),
+            ("let a = [1, 2]; a[0] = 5; a[1] += 1; a", "[5, 3]"),
+            ("let a = [1]; let b = a; b[0] = 2; a", "[2]"),
+            ("let m = [[1, 2], [3]]; m[0][1] = 9; m", "[[1, 9], [3]]"),
+            (
+                "let a = [1]; a[1] = 2",
+                "ERROR: index out of range: 1 (length 1)",
+            ),
+            (
+                "let h = {\"a\": 1, true: 2, 3: \"c\"}; [h[\"a\"], h[true], h[3], h[4]]",
+                "[1, 2, \"c\", null]",
+            ),
+            (
+                "let h = {\"b\": 1, \"a\": 2}; h[\"c\"] = 3; h[\"b\"] *= 10; h",
+                "{\"b\": 10, \"a\": 2, \"c\": 3}",
+            ),
+            ("{1: \"x\", 1: \"y\"}", "{1: \"y\"}"),
+        ]);
+    }
+    #[test]
+    fn it_keys_hashes_by_value() {
+        assert_runs(vec![
+            (
+                "let h = {1: \"int\", \"1\": \"string\", true: \"bool\"}; [len(h), h[1], h[\"1\"], h[1 == 1]]",
+                "[3, \"int\", \"string\", \"bool\"]",
+            ),
+            ("let k = \"na\"; {\"name\": 1}[k + \"me\"]", "1"),
+            (
+                "{3: \"c\", 1: \"a\", 2: \"b\"}",
+                "{3: \"c\", 1: \"a\", 2: \"b\"}",
+            ),
+            (
+                "let h = {\"z\": 1, \"a\": 2}; h[\"m\"] = 3; h[\"z\"] = 4; h",
+                "{\"z\": 4, \"a\": 2, \"m\": 3}",
+            ),
+            ("{1: 2, 3: 4} == {3: 4, 1: 2}", "true"),
+            ("{1: 2} == {1: 3}", "false"),
+            ("{[1]: 2}", "ERROR: unusable as hash key: ARRAY"),
+            ("{1: 2}[fn(x) { x }]", "ERROR: unusable as hash key: FUNCTION"),
+            ("let h = {}; h[1.5] = 1", "ERROR: unusable as hash key: FLOAT"),
+            ("let h = {}; h[{}] += 1", "ERROR: unusable as hash key: HASH"),
+            ("{1: missing}", "ERROR: identifier not found: missing"),
+        ]);
+
+        let mut pairs = HashPairs::new();
+        pairs.insert(HashKey::String("b".to_string()), Object::Integer(1));
+        pairs.insert(HashKey::Integer(0), Object::Null);
+        pairs.insert(HashKey::String("b".to_string()), Object::Integer(2));
+        assert_eq!(pairs.len(), 2);
+        assert_eq!(
+            pairs.get(&HashKey::String("b".to_string())),
+            Some(&Object::Integer(2))
+        );
+        assert_eq!(
+            pairs.iter().next().unwrap().0,
+            HashKey::String("b".to_string())
+        );
+        assert_eq!(
+            Object::from(HashKey::Boolean(true)).hash_key(),
+            Some(HashKey::Boolean(true))
+        );
+        assert_eq!(Object::Float(1.0).hash_key(), None);
+    }
+    #[test]
+    fn it_handles_containers_that_hold_themselves() {
+        assert_runs(vec![
+            ("let a = [1]; push(a, a); a", "[1, [...]]"),
+            ("let h = {}; h[1] = h; h", "{1: {...}}"),
+            (
+                "let a = [1]; let h = {\"a\": a}; push(a, h); h",
+                "{\"a\": [1, {...}]}",
+            ),
+            ("let a = [1]; let b = [a, a]; b", "[[1], [1]]"),
+            ("let a = [1]; push(a, a); a == a", "true"),
+            ("let h = {}; h[1] = h; h != h", "false"),
+            (
+                "let a = [1]; push(a, a); let b = [1]; push(b, b); a == b",
+                "true",
+            ),
+            (
+                "let a = [1]; push(a, a); let b = [2]; push(b, b); a == b",
+                "false",
+            ),
+            ("let g = {}; g[1] = g; let h = {}; h[1] = h; g == h", "true"),
+        ]);
+    }
+    #[test]
+    fn it_calls_functions_and_builtins() {
+        assert_runs(vec![
+            ("let add = fn(a, b) { a + b }; add(2, add(3, 4))", "9"),
+            ("let f = fn(x) { return x * 2; 0 }; f(5)", "10"),
+            (
+                "let fact = fn(n) { if n < 2 { 1 } else { n * fact(n - 1) } }; fact(10)",
+                "3628800",
+            ),
+            ("let x = 1; let f = fn() { x = 2 }; f(); x", "2"),
+            // Names resolve where the function was written, not where it's called
+            (
+                "let x = 1; let f = fn() { x }; let g = fn() { let x = 2; f() }; g()",
+                "1",
+            ),
+            (
+                "let x = 1; let set = fn() { x = 5 }; let g = fn() { let x = 2; set(); x }; [g(), x]",
+                "[2, 5]",
+            ),
+            ("fn(x) { x }", "fn(x) {x}"),
+            ("len(\"héllo\") + len([1, 2]) + len({1: 2})", "8"),
+            (
+                "[first([1, 2]), last([1, 2]), rest([1, 2, 3]), first([])]",
+                "[1, 2, [2, 3], null]",
+            ),
+            ("let a = [1]; push(a, 2); a", "[1, 2]"),
+            ("len", "builtin function len"),
+            (
+                "len(1)",
+                "ERROR: argument to `len` not supported, got INTEGER",
+            ),
+            (
+                "len()",
+                "ERROR: wrong number of arguments to `len`: want=1, got=0",
+            ),
+        ]);
+    }
+    #[test]
+    fn it_captures_environments_in_closures() {
+        assert_runs(vec![
+            (
+                "let adder = fn(x) { fn(y) { x + y } }; let add2 = adder(2); add2(3)",
+                "5",
+            ),
+            (
+                "let counter = fn() { let n = 0; fn() { n++; n } }; let c = counter(); c(); c(); c()",
+                "3",
+            ),
+            (
+                "let counter = fn() { let n = 0; fn() { n++; n } }; let a = counter(); let b = counter(); a(); a(); b()",
+                "1",
+            ),
+            (
+                "let make = fn() { let n = 0; [fn() { n += 10 }, fn() { n }] }; let fs = make(); fs[0](); fs[0](); fs[1]()",
+                "20",
+            ),
+            (
+                "let x = 1; let get = fn() { x }; x = 2; get()",
+                "2",
+            ),
+            (
+                "let f = fn() { y }; let g = fn() { let y = 1; f() }; g()",
+                "ERROR: identifier not found: y",
+            ),
+            (
+                "let fs = []; for (i in [1, 2, 3]) { push(fs, fn() { i * 10 }) } [fs[0](), fs[2]()]",
+                "[10, 30]",
+            ),
+            (
+                "let f = fn() { let down = fn(n) { if n == 0 { 0 } else { down(n - 1) } }; down(3) }; f()",
+                "0",
+            ),
+        ]);
+    }
+    #[test]
+    fn it_enforces_constants_at_runtime() {
+        // Each of these gets past the parser's static check
+        assert_runs(vec![
+            (
+                "let f = fn() { x = 2 }; let const x = 1; f()",
+                "ERROR: cannot modify constant `x` declared at 1:35",
+            ),
+            (
+                "let f = fn() { x++ }; let const x = 1; f()",
+                "ERROR: cannot modify constant `x` declared at 1:33",
+            ),
+            (
+                "let f = fn() { a[0] = 2 }; let const a = [1]; f()",
+                "ERROR: cannot modify constant `a` declared at 1:38",
+            ),
+            (
+                "let f = fn() { h[\"k\"] += 1 }; let const h = {\"k\": 1}; f(); h",
+                "ERROR: cannot modify constant `h` declared at 1:41",
+            ),
+            (
+                "if true { let const x = 1 } x = 2",
+                "ERROR: cannot modify constant `x` declared at 1:21",
+            ),
+            (
+                "if true { let const x = 1 } let x = 2",
+                "ERROR: cannot redeclare constant `x` declared at 1:21",
+            ),
+            ("let const x = 1; let f = fn(x) { x = 2; x }; f(0)", "2"),
+            (
+                "let const x = 1; let f = fn() { let x = 5; x++; x }; f()",
+                "6",
+            ),
+            ("let const a = [1]; let b = a; b[0] = 2; a", "[2]"),
+            // Each iteration of a loop body declares its constants afresh
+            (
+                "let i = 0; let sum = 0; while i < 3 { let const y = i * 2; sum += y; i++ } sum",
+                "6",
+            ),
+            (
+                "let sum = 0; for (let j = 0; j < 3; j++) { let const z = j; sum += z } sum",
+                "3",
+            ),
+            (
+                "let out = []; for (x in [1, 2]) { let const y = x; push(out, y) } out",
+                "[1, 2]",
+            ),
+        ]);
+
+        let env = Environment::new();
+        env.borrow_mut()
+            .set_const("limit", Object::Integer(10), Span::new(10, 15, 1, 11));
+        assert_eq!(
+            env.borrow_mut().assign("limit", Object::Integer(11)),
+            Err(AssignError::Constant(Span::new(10, 15, 1, 11)))
+        );
+        assert_eq!(
+            env.borrow_mut().assign("missing", Object::Null),
+            Err(AssignError::Undefined)
+        );
+        assert_eq!(env.borrow().get("limit"), Some(Object::Integer(10)));
+    }
+    #[test]
+    fn it_runs_loops() {
+        assert_runs(vec![
+            ("let n = 0; while n < 5 { n++ } n", "5"),
+            (
+                "let sum = 0; for (let i = 0; i < 10; i++) { if i % 2 == 0 { continue } sum += i } sum",
+                "25",
+            ),
+            ("for (let i = 0; i < 3; i++) {} i", "ERROR: identifier not found: i"),
+            (
+                "let n = 0; while true { n++; if n == 3 { break } } n",
+                "3",
+            ),
+            (
+                "let out = []; for (i, x in [\"a\", \"b\"]) { push(out, [i, x]) } out",
+                "[[0, \"a\"], [1, \"b\"]]",
+            ),
+            (
+                "let out = []; for (k in {\"a\": 1, \"b\": 2}) { push(out, k) } out",
+                "[\"a\", \"b\"]",
+            ),
+            (
+                "let out = 0; for (k, v in {\"a\": 1, \"b\": 2}) { out += v } out",
+                "3",
+            ),
+            ("let s = \"\"; for (c in \"abc\") { s = c + s } s", "cba"),
+            (
+                "let f = fn() { for (x in [1, 2, 3]) { if x == 2 { return x } } }; f()",
+                "2",
+            ),
+            (
+                "let n = 0; while true { while true { break } n++; if n > 1 { break } } n",
+                "2",
+            ),
+        ]);
+    }
+}
@@ -1,5 +1,8 @@
-use std::collections::HashMap;
-use tokens::{lookup_keyword, Token, TokenType};
+#![allow(clippy::needless_return)]
+
+use std::{borrow::Cow, collections::HashMap, error::Error, fmt, iter::FusedIterator};
+use tokens::{lookup_keyword, parse_int, Radix, Span, Token, TokenType};
+use unicode_xid::UnicodeXID;
 
 lazy_static::lazy_static! {
     static ref SINGLE_OPS: HashMap<char, Token<'static>> = {
@@ -9,7 +12,12 @@ lazy_static::lazy_static! {
         ops.insert('+', Token::new(TokenType::Plus, "+"));
         ops.insert('*', Token::new(TokenType::Mult, "*"));
         ops.insert('/', Token::new(TokenType::Div, "/"));
+        ops.insert('%', Token::new(TokenType::Mod, "%"));
         ops.insert('!', Token::new(TokenType::Bang, "!"));
+        ops.insert('&', Token::new(TokenType::BitAnd, "&"));
+        ops.insert('|', Token::new(TokenType::BitOr, "|"));
+        ops.insert('^', Token::new(TokenType::BitXor, "^"));
+        ops.insert('~', Token::new(TokenType::BitNot, "~"));
         ops.insert('<', Token::new(TokenType::LessThan, "<"));
         ops.insert('>', Token::new(TokenType::GreaterThan, ">"));
         ops.insert(';', Token::new(TokenType::Semicolon, ";"));
@@ -23,66 +31,172 @@ lazy_static::lazy_static! {
         ops.insert(':', Token::new(TokenType::Colon, ":"));
         return ops
     };
+
+    static ref DOUBLE_OPS: HashMap<(char, char), Token<'static>> = {
+        let mut ops = HashMap::new();
+        ops.insert(('=', '='), Token::new(TokenType::Eq, "=="));
+        ops.insert(('!', '='), Token::new(TokenType::NotEq, "!="));
+        ops.insert(('+', '+'), Token::new(TokenType::Increment, "++"));
+        ops.insert(('-', '-'), Token::new(TokenType::Decrement, "--"));
+        ops.insert(('<', '='), Token::new(TokenType::LessEq, "<="));
+        ops.insert(('>', '='), Token::new(TokenType::GreaterEq, ">="));
+        ops.insert(('&', '&'), Token::new(TokenType::And, "&&"));
+        ops.insert(('|', '|'), Token::new(TokenType::Or, "||"));
+        ops.insert(('*', '*'), Token::new(TokenType::Pow, "**"));
+        ops.insert(('<', '<'), Token::new(TokenType::ShiftLeft, "<<"));
+        ops.insert(('>', '>'), Token::new(TokenType::ShiftRight, ">>"));
+        ops.insert(('+', '='), Token::new(TokenType::PlusAssign, "+="));
+        ops.insert(('-', '='), Token::new(TokenType::MinusAssign, "-="));
+        ops.insert(('*', '='), Token::new(TokenType::MultAssign, "*="));
+        ops.insert(('/', '='), Token::new(TokenType::DivAssign, "/="));
+        ops.insert(('%', '='), Token::new(TokenType::ModAssign, "%="));
+        return ops
+    };
+}
+
+/// Something the lexer could not make sense of. Each error is also emitted
+/// in the token stream as an `Illegal` token holding the offending text.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexError {
+    UnexpectedCharacter {
+        ch: char,
+        span: Span,
+    },
+    UnterminatedComment {
+        span: Span,
+    },
+    UnterminatedString {
+        span: Span,
+    },
+    InvalidEscape {
+        sequence: String,
+        span: Span,
+    },
+    InvalidNumber {
+        literal: String,
+        reason: &'static str,
+        span: Span,
+    },
+}
+
+impl LexError {
+    pub fn span(&self) -> Span {
+        return match self {
+            LexError::UnexpectedCharacter { span, .. } => *span,
+            LexError::UnterminatedComment { span } => *span,
+            LexError::UnterminatedString { span } => *span,
+            LexError::InvalidEscape { span, .. } => *span,
+            LexError::InvalidNumber { span, .. } => *span,
+        };
+    }
+
+    /// The error description without its location.
+    pub fn message(&self) -> String {
+        return match self {
+            LexError::UnexpectedCharacter { ch, .. } => {
+                format!("unexpected character `{}`", ch.escape_debug())
+            }
+            LexError::UnterminatedComment { .. } => "unterminated block comment".to_string(),
+            LexError::UnterminatedString { .. } => "unterminated string".to_string(),
+            LexError::InvalidEscape { sequence, .. } => {
+                format!("invalid escape sequence `{}`", sequence)
+            }
+            LexError::InvalidNumber {
+                literal, reason, ..
+            } => format!("invalid number `{}`: {}", literal, reason),
+        };
+    }
 }
 
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.span(), self.message())
+    }
+}
+
+impl Error for LexError {}
+
 pub struct Lexer<'a> {
     input: &'a str,
     pub position: usize,
     pub read_position: usize,
     cur_char: char,
+
+    // 1-based location of `cur_char`
+    line: usize,
+    column: usize,
+
+    errors: Vec<LexError>,
+    // Set once `Eof` has been yielded, after which the iterator is done
+    finished: bool,
 }
 
 impl<'a> Iterator for Lexer<'a> {
     type Item = Token<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.skip_whitespace();
+        if self.finished {
+            return None;
+        }
 
-        let token = if let Some(single_operator) = SINGLE_OPS.get(&self.cur_char) {
-            let peeked = self.read_char(true);
+        if let Err(err) = self.skip_whitespace() {
+            let span = err.span();
+            self.errors.push(err);
+            return Some(
+                Token::new(TokenType::Illegal, &self.input[span.start..span.end]).with_span(span),
+            );
+        }
 
-            if self.cur_char == '=' && peeked == '=' {
-                self.read_char(false);
-                Token::new(TokenType::Eq, "==")
-            } else if self.cur_char == '!' && peeked == '=' {
-                self.read_char(false);
-                Token::new(TokenType::NotEq, "!=")
-            } else if self.cur_char == '+' && peeked == '+' {
-                self.read_char(false);
-                Token::new(TokenType::Increment, "++")
-            } else if self.cur_char == '-' && peeked == '-' {
-                self.read_char(false);
-                Token::new(TokenType::Decrement, "--")
-            } else {
-                single_operator.clone()
-            }
+        let (start, line, column) = (self.position, self.line, self.column);
+
+        let peeked = self.read_char(true);
+
+        let token = if let Some(double_operator) = DOUBLE_OPS.get(&(self.cur_char, peeked)) {
+            self.read_char(false);
+            self.read_char(false);
+            double_operator.clone()
+        } else if let Some(single_operator) = SINGLE_OPS.get(&self.cur_char) {
+            self.read_char(false);
+            single_operator.clone()
         } else if self.cur_char == '\0' {
+            self.finished = true;
             Token::new(TokenType::Eof, "\0")
         } else if self.cur_char == '\'' || self.cur_char == '\"' {
-            Token::new(TokenType::String, self.read_string())
-        } else if self.cur_char.is_alphabetic() {
+            match self.read_string() {
+                Some(value) => Token::new(TokenType::String, value),
+                None => Token::new(TokenType::Illegal, &self.input[start..self.position]),
+            }
+        } else if is_ident_start(self.cur_char) {
             let start = self.position;
 
-            while self.cur_char.is_alphabetic() {
+            while is_ident_continue(self.cur_char) {
                 self.read_char(false);
             }
 
             let literal = &self.input[start..self.position];
             Token::new(lookup_keyword(literal), literal)
-        } else if self.cur_char.is_digit(10) {
-            let start = self.position;
-            while self.cur_char.is_digit(10) {
-                self.read_char(false);
-            }
-            Token::new(TokenType::Int, &self.input[start..self.position])
+        } else if self.cur_char.is_ascii_digit()
+            || (self.cur_char == '.' && peeked.is_ascii_digit())
+        {
+            let token_type = self.read_number().unwrap_or(TokenType::Illegal);
+            Token::new(token_type, &self.input[start..self.position])
         } else {
-            Token::new(TokenType::Illegal, "?")
+            let ch = self.cur_char;
+            self.read_char(false);
+
+            self.errors.push(LexError::UnexpectedCharacter {
+                ch,
+                span: Span::new(start, self.position, line, column),
+            });
+            Token::new(TokenType::Illegal, &self.input[start..self.position])
         };
 
-        Some(token)
+        Some(token.with_span(Span::new(start, self.position, line, column)))
     }
 }
 
+impl FusedIterator for Lexer<'_> {}
+
 impl<'a> Lexer<'a> {
     pub fn new(input: &'a str) -> Lexer<'a> {
         let mut new = Lexer {
@@ -90,59 +204,363 @@ impl<'a> Lexer<'a> {
             position: 0,
             read_position: 0,
             cur_char: '\0',
+
+            line: 1,
+            column: 1,
+
+            errors: Vec::new(),
+            finished: false,
         };
 
         new.read_char(false);
-        return new
+        return new;
+    }
+
+    /// Lexes the whole input, ending with the `Eof` token, or returns every
+    /// error found along the way.
+    pub fn tokenize(input: &'a str) -> Result<Vec<Token<'a>>, Vec<LexError>> {
+        let mut lexer = Lexer::new(input);
+        let tokens: Vec<Token<'a>> = lexer.by_ref().collect();
+
+        if lexer.errors.is_empty() {
+            return Ok(tokens);
+        }
+        return Err(lexer.errors);
+    }
+
+    pub fn errors(&self) -> &[LexError] {
+        return &self.errors;
     }
 
     fn read_char(&mut self, peek: bool) -> char {
-        if self.read_position >= self.input.len() {
-            if peek {
-                return '\0';
-            }
-            self.cur_char = '\0';
-        } else {
-            let read = self.input[self.read_position..].chars().next().unwrap();
-            if peek {
-                return read;
+        let read = self.input[self.read_position..]
+            .chars()
+            .next()
+            .unwrap_or('\0');
+        if peek {
+            return read;
+        }
+
+        // Only move the line/column forward when we actually step over a
+        // character, so the initial read and repeated reads at EOF stay put
+        if self.read_position > self.position {
+            if self.cur_char == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
             }
-            self.cur_char = read;
         }
 
+        self.cur_char = read;
         self.position = self.read_position;
-        self.read_position += self.cur_char.len_utf8();
-        return self.cur_char
+        if self.read_position < self.input.len() {
+            self.read_position += read.len_utf8();
+        }
+        return self.cur_char;
     }
 
-    fn read_string(&mut self) -> &'a str {
-        let start = self.position + 1;
+    // Reads a quoted string and decodes its escape sequences. The value is
+    // only copied out of the input once an escape forces it to differ.
+    // Returns None after recording an error if the string is malformed.
+    fn read_string(&mut self) -> Option<Cow<'a, str>> {
+        let (start, line, column) = (self.position, self.line, self.column);
+        let input = self.input;
+        let quote = self.cur_char;
+
+        self.read_char(false);
+        let content_start = self.position;
+        let mut unescaped: Option<String> = None;
+        let mut valid = true;
 
         loop {
-            let read = self.read_char(false);
+            match self.cur_char {
+                '\0' => {
+                    self.errors.push(LexError::UnterminatedString {
+                        span: Span::new(start, self.position, line, column),
+                    });
+                    return None;
+                }
+                '\\' => {
+                    let value = unescaped
+                        .get_or_insert_with(|| input[content_start..self.position].to_string());
+
+                    match self.read_escape() {
+                        Some(Ok(ch)) => value.push(ch),
+                        Some(Err(err)) => {
+                            self.errors.push(err);
+                            valid = false;
+                        }
+                        // The input ended, which the next pass reports
+                        None => {}
+                    }
+                    continue;
+                }
+                ch if ch == quote => break,
+                ch => {
+                    if let Some(value) = unescaped.as_mut() {
+                        value.push(ch);
+                    }
+                }
+            }
+
+            self.read_char(false);
+        }
+
+        let content_end = self.position;
+        // Step over the closing quote
+        self.read_char(false);
+
+        if !valid {
+            return None;
+        }
+
+        return Some(match unescaped {
+            Some(value) => Cow::Owned(value),
+            None => Cow::Borrowed(&input[content_start..content_end]),
+        });
+    }
+
+    // Decodes the escape sequence starting at the current `\`, leaving the
+    // lexer on the character right after it. Returns None if the input ends
+    // right after the `\`, leaving that for the string loop to report as
+    // unterminated.
+    fn read_escape(&mut self) -> Option<Result<char, LexError>> {
+        let (start, line, column) = (self.position, self.line, self.column);
+
+        let escaped = match self.read_char(false) {
+            'n' => Some('\n'),
+            't' => Some('\t'),
+            'r' => Some('\r'),
+            '0' => Some('\0'),
+            '\\' => Some('\\'),
+            '"' => Some('"'),
+            '\'' => Some('\''),
+            'u' => self.read_unicode_escape(),
+            '\0' => return None,
+            _ => {
+                self.read_char(false);
+                None
+            }
+        };
+
+        if let Some(ch) = escaped {
+            self.read_char(false);
+            return Some(Ok(ch));
+        }
+
+        return Some(Err(LexError::InvalidEscape {
+            sequence: self.input[start..self.position].to_string(),
+            span: Span::new(start, self.position, line, column),
+        }));
+    }
 
-            if read == self.cur_char || read == '\0' {
-                break;
+    // Reads the `{XXXX}` part of a `\u{XXXX}` escape, with 1 to 6 hex digits.
+    // On success the lexer is left on the closing brace.
+    fn read_unicode_escape(&mut self) -> Option<char> {
+        if self.read_char(false) != '{' {
+            return None;
+        }
+
+        self.read_char(false);
+        let digits_start = self.position;
+        while self.cur_char.is_ascii_hexdigit() {
+            self.read_char(false);
+        }
+        let digits = &self.input[digits_start..self.position];
+
+        if self.cur_char != '}' {
+            return None;
+        }
+        if digits.is_empty() || digits.len() > 6 {
+            self.read_char(false);
+            return None;
+        }
+
+        let ch = u32::from_str_radix(digits, 16)
+            .ok()
+            .and_then(char::from_u32);
+        if ch.is_none() {
+            self.read_char(false);
+        }
+        return ch;
+    }
+
+    // Reads an integer or float literal. A float needs digits after its `.`
+    // (`.5` is fine, `5.` is not) and may carry an exponent, as in `2.5e-3`.
+    // Integers may use a `0b`, `0o` or `0x` prefix, and any digits may be
+    // separated by `_`. Returns None after recording an error if the literal
+    // is malformed.
+    fn read_number(&mut self) -> Option<TokenType> {
+        let (start, line, column) = (self.position, self.line, self.column);
+        let mut token_type = TokenType::Int;
+
+        if self.cur_char == '0' && matches!(self.read_char(true), 'b' | 'o' | 'x') {
+            return self.read_radix_integer();
+        }
+
+        self.read_digits();
+
+        if self.cur_char == '.' {
+            token_type = TokenType::Float;
+            self.read_char(false);
+
+            if !self.read_digits() {
+                return self.invalid_number(start, line, column, "expected digits after `.`");
+            }
+        }
+
+        if self.cur_char == 'e' || self.cur_char == 'E' {
+            token_type = TokenType::Float;
+            self.read_char(false);
+
+            if self.cur_char == '+' || self.cur_char == '-' {
+                self.read_char(false);
             }
+            if !self.read_digits() {
+                return self.invalid_number(start, line, column, "expected digits in exponent");
+            }
+        }
+
+        if token_type == TokenType::Int && parse_int(&self.input[start..self.position]).is_none() {
+            return self.invalid_number(start, line, column, "integer literal is out of range");
         }
 
-        return &self.input[start..self.position];
+        return Some(token_type);
     }
 
-    fn skip_whitespace(&mut self) {
-        while self.cur_char == ' '
-            || self.cur_char == '\t'
-            || self.cur_char == '\n'
-            || self.cur_char == '\r'
+    fn read_radix_integer(&mut self) -> Option<TokenType> {
+        let (start, line, column) = (self.position, self.line, self.column);
+
+        // Step over the prefix, then take every alphanumeric so that a stray
+        // digit like the `2` in `0b102` is reported instead of split off
+        self.read_char(false);
+        self.read_char(false);
+        while self.cur_char.is_ascii_alphanumeric() || self.cur_char == '_' {
+            self.read_char(false);
+        }
+
+        let literal = &self.input[start..self.position];
+        let (radix, digits) = Radix::split(literal);
+
+        let reason = if !digits.chars().any(|ch| ch != '_') {
+            "expected digits after the radix prefix"
+        } else if !digits
+            .chars()
+            .all(|ch| ch == '_' || ch.is_digit(radix.value()))
         {
+            match radix {
+                Radix::Binary => "invalid digit for a binary literal",
+                Radix::Octal => "invalid digit for an octal literal",
+                _ => "invalid digit for a hexadecimal literal",
+            }
+        } else if parse_int(literal).is_none() {
+            "integer literal is out of range"
+        } else {
+            return Some(TokenType::Int);
+        };
+
+        return self.invalid_number(start, line, column, reason);
+    }
+
+    // Reads digits and `_` separators, which must start with a digit.
+    // Returns whether anything was read.
+    fn read_digits(&mut self) -> bool {
+        if !self.cur_char.is_ascii_digit() {
+            return false;
+        }
+
+        while self.cur_char.is_ascii_digit() || self.cur_char == '_' {
+            self.read_char(false);
+        }
+        return true;
+    }
+
+    fn invalid_number(
+        &mut self,
+        start: usize,
+        line: usize,
+        column: usize,
+        reason: &'static str,
+    ) -> Option<TokenType> {
+        self.errors.push(LexError::InvalidNumber {
+            literal: self.input[start..self.position].to_string(),
+            reason,
+            span: Span::new(start, self.position, line, column),
+        });
+        return None;
+    }
+
+    // Skips whitespace along with `// line` and `/* block */` comments
+    fn skip_whitespace(&mut self) -> Result<(), LexError> {
+        loop {
+            match (self.cur_char, self.read_char(true)) {
+                (' ' | '\t' | '\n' | '\r', _) => {
+                    self.read_char(false);
+                }
+                ('/', '/') => self.skip_line_comment(),
+                ('/', '*') => self.skip_block_comment()?,
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn skip_line_comment(&mut self) {
+        while self.cur_char != '\n' && self.cur_char != '\0' {
             self.read_char(false);
         }
     }
+
+    // Block comments nest, so `/* a /* b */ c */` is a single comment
+    fn skip_block_comment(&mut self) -> Result<(), LexError> {
+        let (start, line, column) = (self.position, self.line, self.column);
+        let mut depth = 0;
+
+        loop {
+            match (self.cur_char, self.read_char(true)) {
+                ('\0', _) => {
+                    return Err(LexError::UnterminatedComment {
+                        span: Span::new(start, self.position, line, column),
+                    });
+                }
+                ('/', '*') => {
+                    depth += 1;
+                    self.read_char(false);
+                }
+                ('*', '/') => {
+                    depth -= 1;
+                    self.read_char(false);
+                }
+                _ => {}
+            }
+
+            self.read_char(false);
+            if depth == 0 {
+                return Ok(());
+            }
+        }
+    }
+}
+
+// Identifiers follow Unicode's XID rules, with `_` also allowed to start one
+fn is_ident_start(ch: char) -> bool {
+    return ch == '_' || ch.is_xid_start();
+}
+
+fn is_ident_continue(ch: char) -> bool {
+    return ch.is_xid_continue();
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    // Hand-built tokens carry no span, so only compare what they describe
+    fn assert_tokens_eq(token: &Token, expected: &Token) {
+        assert_eq!(token.token_type, expected.token_type);
+        assert_eq!(token.literal, expected.literal);
+    }
+
     #[test]
     fn it_can_do_basic_symbols() {
         let input = "=+(){},;";
@@ -159,10 +577,8 @@ mod tests {
             Token::new(TokenType::Eof, "\0"),
         ];
         for expected in expects {
-            let tok = lexer.next().unwrap();
-												println!("{tok}, {}", lexer.position);
-						
-            // assert_eq!(tok, expected);
+            let token = lexer.next().unwrap();
+            assert_tokens_eq(&token, &expected);
         }
     }
     #[test]
@@ -217,13 +633,13 @@ mod tests {
         let mut lexer = Lexer::new(input);
         for expected in expects {
             let token = lexer.next().unwrap();
-            assert_eq!(token, expected);
+            assert_tokens_eq(&token, &expected);
         }
     }
     #[test]
     fn it_can_do_operators() {
         let input = r#"
-            !-/*5;
+            !-/ *5;
             5 < 10 > 5;
         "#;
         let expects = vec![
@@ -243,7 +659,7 @@ mod tests {
         let mut lexer = Lexer::new(input);
         for expected in expects {
             let token = lexer.next().unwrap();
-            assert_eq!(token, expected);
+            assert_tokens_eq(&token, &expected);
         }
     }
     #[test]
@@ -277,7 +693,7 @@ mod tests {
         let mut lexer = Lexer::new(input);
         for expected in expects {
             let token = lexer.next().unwrap();
-            assert_eq!(token, expected);
+            assert_tokens_eq(&token, &expected);
         }
     }
     #[test]
@@ -299,7 +715,7 @@ mod tests {
         let mut lexer = Lexer::new(input);
         for expected in expects {
             let token = lexer.next().unwrap();
-            assert_eq!(token, expected);
+            assert_tokens_eq(&token, &expected);
         }
     }
     #[test]
@@ -358,7 +774,463 @@ mod tests {
         let mut lexer = Lexer::new(input);
         for expected in expects {
             let token = lexer.next().unwrap();
-            assert_eq!(token, expected);
+            assert_tokens_eq(&token, &expected);
         }
     }
-}
\ No newline at end of file
+    #[test]
+    fn it_tracks_token_spans() {
+        let input = "let x = 10;\n  \"héllo\" ==\nfoo";
+        let expects = vec![
+            (TokenType::Let, Span::new(0, 3, 1, 1)),
+            (TokenType::Ident, Span::new(4, 5, 1, 5)),
+            (TokenType::Assign, Span::new(6, 7, 1, 7)),
+            (TokenType::Int, Span::new(8, 10, 1, 9)),
+            (TokenType::Semicolon, Span::new(10, 11, 1, 11)),
+            (TokenType::String, Span::new(14, 22, 2, 3)),
+            (TokenType::Eq, Span::new(23, 25, 2, 11)),
+            (TokenType::Ident, Span::new(26, 29, 3, 1)),
+            (TokenType::Eof, Span::new(29, 29, 3, 4)),
+        ];
+        let mut lexer = Lexer::new(input);
+        for (token_type, span) in expects {
+            let token = lexer.next().unwrap();
+            assert_eq!(token.token_type, token_type);
+            assert_eq!(token.span, span);
+        }
+    }
+    #[test]
+    fn it_skips_comments() {
+        let input = r#"
+            // a line comment
+            let x = 5; // trailing
+            /* a block
+               /* with a nested */ comment */
+            x / 2 /**/
+            // comment at EOF"#;
+        let expects = vec![
+            Token::new(TokenType::Let, "let"),
+            Token::new(TokenType::Ident, "x"),
+            Token::new(TokenType::Assign, "="),
+            Token::new(TokenType::Int, "5"),
+            Token::new(TokenType::Semicolon, ";"),
+            Token::new(TokenType::Ident, "x"),
+            Token::new(TokenType::Div, "/"),
+            Token::new(TokenType::Int, "2"),
+            Token::new(TokenType::Eof, "\0"),
+        ];
+        let mut lexer = Lexer::new(input);
+        for expected in expects {
+            let token = lexer.next().unwrap();
+            assert_tokens_eq(&token, &expected);
+        }
+        assert!(lexer.errors().is_empty());
+    }
+    #[test]
+    fn it_reports_unterminated_block_comments() {
+        let input = "x /* one /* two */ never closed";
+        let mut lexer = Lexer::new(input);
+
+        assert_tokens_eq(&lexer.next().unwrap(), &Token::new(TokenType::Ident, "x"));
+
+        let token = lexer.next().unwrap();
+        assert_tokens_eq(
+            &token,
+            &Token::new(TokenType::Illegal, "/* one /* two */ never closed"),
+        );
+        assert_eq!(token.span, Span::new(2, 31, 1, 3));
+        assert_eq!(
+            lexer.errors(),
+            &[LexError::UnterminatedComment {
+                span: Span::new(2, 31, 1, 3)
+            }]
+        );
+
+        assert_eq!(lexer.next().unwrap().token_type, TokenType::Eof);
+    }
+    #[test]
+    fn it_can_unescape_strings() {
+        let input = r#"
+            "line\nbreak\ttab"
+            'single \'quoted\' "double"'
+            "back\\slash\0"
+            "\u{1F600} \u{e9}"
+            "no escapes"
+        "#;
+        let expects = vec![
+            Token::new(TokenType::String, "line\nbreak\ttab"),
+            Token::new(TokenType::String, "single 'quoted' \"double\""),
+            Token::new(TokenType::String, "back\\slash\0"),
+            Token::new(TokenType::String, "\u{1F600} \u{e9}"),
+            Token::new(TokenType::String, "no escapes"),
+        ];
+        let mut lexer = Lexer::new(input);
+        for expected in expects {
+            let token = lexer.next().unwrap();
+            assert_tokens_eq(&token, &expected);
+        }
+        assert!(lexer.errors().is_empty());
+    }
+    #[test]
+    fn it_reports_invalid_escapes() {
+        let input = r#"x = "bad \q and \u{110000} and \u{zz}"; y"#;
+        let mut lexer = Lexer::new(input);
+
+        lexer.next();
+        lexer.next();
+        let token = lexer.next().unwrap();
+        assert_tokens_eq(
+            &token,
+            &Token::new(TokenType::Illegal, r#""bad \q and \u{110000} and \u{zz}""#),
+        );
+        assert_eq!(token.span, Span::new(4, 38, 1, 5));
+        assert_eq!(
+            lexer.errors(),
+            &[
+                LexError::InvalidEscape {
+                    sequence: r"\q".to_string(),
+                    span: Span::new(9, 11, 1, 10),
+                },
+                LexError::InvalidEscape {
+                    sequence: r"\u{110000}".to_string(),
+                    span: Span::new(16, 26, 1, 17),
+                },
+                LexError::InvalidEscape {
+                    sequence: r"\u{".to_string(),
+                    span: Span::new(31, 34, 1, 32),
+                },
+            ]
+        );
+
+        assert_tokens_eq(
+            &lexer.next().unwrap(),
+            &Token::new(TokenType::Semicolon, ";"),
+        );
+        assert_tokens_eq(&lexer.next().unwrap(), &Token::new(TokenType::Ident, "y"));
+    }
+    #[test]
+    fn it_reports_unterminated_strings() {
+        let input = "\"never closed \\\"";
+        let mut lexer = Lexer::new(input);
+
+        let token = lexer.next().unwrap();
+        assert_tokens_eq(&token, &Token::new(TokenType::Illegal, input));
+        assert_eq!(
+            lexer.errors(),
+            &[LexError::UnterminatedString {
+                span: Span::new(0, 16, 1, 1)
+            }]
+        );
+        assert_eq!(lexer.next().unwrap().token_type, TokenType::Eof);
+
+        // A trailing `\` is part of the unterminated string, not a bad escape
+        let mut lexer = Lexer::new("\"abc\\");
+        assert_eq!(lexer.next().unwrap().token_type, TokenType::Illegal);
+        assert_eq!(
+            lexer.errors(),
+            &[LexError::UnterminatedString {
+                span: Span::new(0, 5, 1, 1)
+            }]
+        );
+    }
+    #[test]
+    fn it_can_lex_identifiers() {
+        let input = "x1 my_var _tmp __ 名前 café fn_ let1 if else 1x";
+        let expects = vec![
+            Token::new(TokenType::Ident, "x1"),
+            Token::new(TokenType::Ident, "my_var"),
+            Token::new(TokenType::Ident, "_tmp"),
+            Token::new(TokenType::Ident, "__"),
+            Token::new(TokenType::Ident, "名前"),
+            Token::new(TokenType::Ident, "café"),
+            Token::new(TokenType::Ident, "fn_"),
+            Token::new(TokenType::Ident, "let1"),
+            Token::new(TokenType::If, "if"),
+            Token::new(TokenType::Else, "else"),
+            Token::new(TokenType::Int, "1"),
+            Token::new(TokenType::Ident, "x"),
+            Token::new(TokenType::Eof, "\0"),
+        ];
+        let mut lexer = Lexer::new(input);
+        for expected in expects {
+            let token = lexer.next().unwrap();
+            assert_tokens_eq(&token, &expected);
+        }
+    }
+    #[test]
+    fn it_can_lex_floats() {
+        let input = "3.14 1e10 2.5e-3 .5 6E+2 10";
+        let expects = vec![
+            Token::new(TokenType::Float, "3.14"),
+            Token::new(TokenType::Float, "1e10"),
+            Token::new(TokenType::Float, "2.5e-3"),
+            Token::new(TokenType::Float, ".5"),
+            Token::new(TokenType::Float, "6E+2"),
+            Token::new(TokenType::Int, "10"),
+            Token::new(TokenType::Eof, "\0"),
+        ];
+        let mut lexer = Lexer::new(input);
+        for expected in expects {
+            let token = lexer.next().unwrap();
+            assert_tokens_eq(&token, &expected);
+        }
+        assert!(lexer.errors().is_empty());
+    }
+    #[test]
+    fn it_reports_malformed_floats() {
+        let input = "5. 1e+;";
+        let mut lexer = Lexer::new(input);
+
+        assert_tokens_eq(
+            &lexer.next().unwrap(),
+            &Token::new(TokenType::Illegal, "5."),
+        );
+        assert_tokens_eq(
+            &lexer.next().unwrap(),
+            &Token::new(TokenType::Illegal, "1e+"),
+        );
+        assert_tokens_eq(
+            &lexer.next().unwrap(),
+            &Token::new(TokenType::Semicolon, ";"),
+        );
+        assert_eq!(
+            lexer.errors(),
+            &[
+                LexError::InvalidNumber {
+                    literal: "5.".to_string(),
+                    reason: "expected digits after `.`",
+                    span: Span::new(0, 2, 1, 1),
+                },
+                LexError::InvalidNumber {
+                    literal: "1e+".to_string(),
+                    reason: "expected digits in exponent",
+                    span: Span::new(3, 6, 1, 4),
+                },
+            ]
+        );
+    }
+    #[test]
+    fn it_can_lex_radix_integers() {
+        let input = "0xFF 0b1010 0o755 1_000_000 0x_dead_beef 9223372036854775807";
+        let expects = vec![
+            Token::new(TokenType::Int, "0xFF"),
+            Token::new(TokenType::Int, "0b1010"),
+            Token::new(TokenType::Int, "0o755"),
+            Token::new(TokenType::Int, "1_000_000"),
+            Token::new(TokenType::Int, "0x_dead_beef"),
+            Token::new(TokenType::Int, "9223372036854775807"),
+            Token::new(TokenType::Eof, "\0"),
+        ];
+        let mut lexer = Lexer::new(input);
+        for expected in expects {
+            let token = lexer.next().unwrap();
+            assert_tokens_eq(&token, &expected);
+        }
+        assert!(lexer.errors().is_empty());
+
+        assert_eq!(parse_int("0xFF"), Some(255));
+        assert_eq!(parse_int("0b1010"), Some(10));
+        assert_eq!(parse_int("0o755"), Some(493));
+        assert_eq!(parse_int("1_000_000"), Some(1_000_000));
+    }
+    #[test]
+    fn it_reports_malformed_integers() {
+        let input = "0b102 0x 0o8 9223372036854775808 0x1_0000_0000_0000_0000";
+        let expects = vec![
+            Token::new(TokenType::Illegal, "0b102"),
+            Token::new(TokenType::Illegal, "0x"),
+            Token::new(TokenType::Illegal, "0o8"),
+            Token::new(TokenType::Illegal, "9223372036854775808"),
+            Token::new(TokenType::Illegal, "0x1_0000_0000_0000_0000"),
+        ];
+        let mut lexer = Lexer::new(input);
+        for expected in expects {
+            let token = lexer.next().unwrap();
+            assert_tokens_eq(&token, &expected);
+        }
+
+        let reasons: Vec<&str> = lexer
+            .errors()
+            .iter()
+            .map(|err| match err {
+                LexError::InvalidNumber { reason, .. } => *reason,
+                _ => panic!("expected an invalid number, got {:?}", err),
+            })
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                "invalid digit for a binary literal",
+                "expected digits after the radix prefix",
+                "invalid digit for an octal literal",
+                "integer literal is out of range",
+                "integer literal is out of range",
+            ]
+        );
+    }
+    #[test]
+    fn it_can_do_comparison_and_logical_operators() {
+        let input = "a <= b >= c && d || !e < f";
+        let expects = vec![
+            Token::new(TokenType::Ident, "a"),
+            Token::new(TokenType::LessEq, "<="),
+            Token::new(TokenType::Ident, "b"),
+            Token::new(TokenType::GreaterEq, ">="),
+            Token::new(TokenType::Ident, "c"),
+            Token::new(TokenType::And, "&&"),
+            Token::new(TokenType::Ident, "d"),
+            Token::new(TokenType::Or, "||"),
+            Token::new(TokenType::Bang, "!"),
+            Token::new(TokenType::Ident, "e"),
+            Token::new(TokenType::LessThan, "<"),
+            Token::new(TokenType::Ident, "f"),
+            Token::new(TokenType::Eof, "\0"),
+        ];
+        let mut lexer = Lexer::new(input);
+        for expected in expects {
+            let token = lexer.next().unwrap();
+            assert_tokens_eq(&token, &expected);
+        }
+    }
+    #[test]
+    fn it_can_do_compound_assignment_operators() {
+        let input = "x += 1; x -= 2; x *= 3; x /= 4; x %= 5; arr[i] += 1";
+        let expects = vec![
+            Token::new(TokenType::Ident, "x"),
+            Token::new(TokenType::PlusAssign, "+="),
+            Token::new(TokenType::Int, "1"),
+            Token::new(TokenType::Semicolon, ";"),
+            Token::new(TokenType::Ident, "x"),
+            Token::new(TokenType::MinusAssign, "-="),
+            Token::new(TokenType::Int, "2"),
+            Token::new(TokenType::Semicolon, ";"),
+            Token::new(TokenType::Ident, "x"),
+            Token::new(TokenType::MultAssign, "*="),
+            Token::new(TokenType::Int, "3"),
+            Token::new(TokenType::Semicolon, ";"),
+            Token::new(TokenType::Ident, "x"),
+            Token::new(TokenType::DivAssign, "/="),
+            Token::new(TokenType::Int, "4"),
+            Token::new(TokenType::Semicolon, ";"),
+            Token::new(TokenType::Ident, "x"),
+            Token::new(TokenType::ModAssign, "%="),
+            Token::new(TokenType::Int, "5"),
+            Token::new(TokenType::Semicolon, ";"),
+            Token::new(TokenType::Ident, "arr"),
+            Token::new(TokenType::LBracket, "["),
+            Token::new(TokenType::Ident, "i"),
+            Token::new(TokenType::RBracket, "]"),
+            Token::new(TokenType::PlusAssign, "+="),
+            Token::new(TokenType::Int, "1"),
+            Token::new(TokenType::Eof, "\0"),
+        ];
+        let mut lexer = Lexer::new(input);
+        for expected in expects {
+            let token = lexer.next().unwrap();
+            assert_tokens_eq(&token, &expected);
+        }
+    }
+    #[test]
+    fn it_can_do_arithmetic_and_bitwise_operators() {
+        let input = "a % b ** c & d | e ^ ~f << 2 >> 1 && g || h";
+        let expects = vec![
+            Token::new(TokenType::Ident, "a"),
+            Token::new(TokenType::Mod, "%"),
+            Token::new(TokenType::Ident, "b"),
+            Token::new(TokenType::Pow, "**"),
+            Token::new(TokenType::Ident, "c"),
+            Token::new(TokenType::BitAnd, "&"),
+            Token::new(TokenType::Ident, "d"),
+            Token::new(TokenType::BitOr, "|"),
+            Token::new(TokenType::Ident, "e"),
+            Token::new(TokenType::BitXor, "^"),
+            Token::new(TokenType::BitNot, "~"),
+            Token::new(TokenType::Ident, "f"),
+            Token::new(TokenType::ShiftLeft, "<<"),
+            Token::new(TokenType::Int, "2"),
+            Token::new(TokenType::ShiftRight, ">>"),
+            Token::new(TokenType::Int, "1"),
+            Token::new(TokenType::And, "&&"),
+            Token::new(TokenType::Ident, "g"),
+            Token::new(TokenType::Or, "||"),
+            Token::new(TokenType::Ident, "h"),
+            Token::new(TokenType::Eof, "\0"),
+        ];
+        let mut lexer = Lexer::new(input);
+        for expected in expects {
+            let token = lexer.next().unwrap();
+            assert_tokens_eq(&token, &expected);
+        }
+    }
+    #[test]
+    fn it_reports_unexpected_characters() {
+        let input = "let x = 5 @ 3;\n#";
+        let expects = vec![
+            Token::new(TokenType::Let, "let"),
+            Token::new(TokenType::Ident, "x"),
+            Token::new(TokenType::Assign, "="),
+            Token::new(TokenType::Int, "5"),
+            Token::new(TokenType::Illegal, "@"),
+            Token::new(TokenType::Int, "3"),
+            Token::new(TokenType::Semicolon, ";"),
+            Token::new(TokenType::Illegal, "#"),
+            Token::new(TokenType::Eof, "\0"),
+        ];
+        let mut lexer = Lexer::new(input);
+        for expected in expects {
+            let token = lexer.next().unwrap();
+            assert_tokens_eq(&token, &expected);
+        }
+
+        assert_eq!(
+            lexer.errors(),
+            &[
+                LexError::UnexpectedCharacter {
+                    ch: '@',
+                    span: Span::new(10, 11, 1, 11),
+                },
+                LexError::UnexpectedCharacter {
+                    ch: '#',
+                    span: Span::new(15, 16, 2, 1),
+                },
+            ]
+        );
+        assert_eq!(
+            lexer.errors()[0].to_string(),
+            "1:11: unexpected character `@`"
+        );
+    }
+    #[test]
+    fn it_stops_after_eof() {
+        let mut lexer = Lexer::new("x");
+
+        assert_eq!(lexer.next().unwrap().token_type, TokenType::Ident);
+        assert_eq!(lexer.next().unwrap().token_type, TokenType::Eof);
+        assert!(lexer.next().is_none());
+        assert!(lexer.next().is_none());
+
+        assert_eq!(Lexer::new("").count(), 1);
+        assert_eq!(Lexer::new("let x = 5;").count(), 6);
+    }
+    #[test]
+    fn it_can_tokenize() {
+        let tokens = Lexer::tokenize("x + 1").unwrap();
+        let types: Vec<TokenType> = tokens.into_iter().map(|token| token.token_type).collect();
+        assert_eq!(
+            types,
+            vec![
+                TokenType::Ident,
+                TokenType::Plus,
+                TokenType::Int,
+                TokenType::Eof
+            ]
+        );
+
+        let errors = Lexer::tokenize("x @ \"open").unwrap_err();
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(
+            errors[0],
+            LexError::UnexpectedCharacter { ch: '@', .. }
+        ));
+        assert!(matches!(errors[1], LexError::UnterminatedString { .. }));
+    }
+}
@@ -1,37 +1,68 @@
+#![allow(clippy::needless_return)]
+
 use ast as AST;
-use lexer::Lexer;
-use std::{any::Any, mem::replace};
-use tokens::{Token, TokenType};
+use lexer::{LexError, Lexer};
+use std::{error::Error, fmt, mem::replace, rc::Rc};
+use tokens::{parse_int, OwnedToken, Radix, Span, TokenType};
 
 use std::collections::HashMap;
 
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 enum Constants {
     LOWEST,
+    OR,
+    AND,
     EQUALS,
     LESSGREATER,
+    BITOR,
+    BITXOR,
+    BITAND,
+    SHIFT,
     SUM,
     PRODUCT,
     PREFIX,
+    POWER,
+    POSTFIX,
     CALL,
     INDEX,
 }
 
-type PrefixParseFn = fn(&mut Parser) -> AST::Expression;
-type InfixParseFn = fn(&mut Parser, expr: AST::Expression) -> AST::Expression;
+type PrefixParseFn<'a> = fn(&mut Parser<'a>) -> Option<AST::Expression>;
+type InfixParseFn<'a> = fn(&mut Parser<'a>, expr: AST::Expression) -> Option<AST::Expression>;
 
 fn get_precedence(token: &TokenType) -> Constants {
     return match token {
+        TokenType::Or => Constants::OR,
+        TokenType::And => Constants::AND,
+
         TokenType::Eq => Constants::EQUALS,
         TokenType::NotEq => Constants::EQUALS,
 
         TokenType::GreaterThan => Constants::LESSGREATER,
         TokenType::LessThan => Constants::LESSGREATER,
+        TokenType::GreaterEq => Constants::LESSGREATER,
+        TokenType::LessEq => Constants::LESSGREATER,
+
+        TokenType::BitOr => Constants::BITOR,
+        TokenType::BitXor => Constants::BITXOR,
+        TokenType::BitAnd => Constants::BITAND,
+
+        TokenType::ShiftLeft => Constants::SHIFT,
+        TokenType::ShiftRight => Constants::SHIFT,
 
         TokenType::Plus => Constants::SUM,
         TokenType::Minus => Constants::SUM,
 
         TokenType::Div => Constants::PRODUCT,
         TokenType::Mult => Constants::PRODUCT,
+        TokenType::Mod => Constants::PRODUCT,
+
+        // Binds tighter than prefix operators so `-2 ** 2` is `-(2 ** 2)`
+        TokenType::Pow => Constants::POWER,
+
+        TokenType::Increment => Constants::POSTFIX,
+        TokenType::Decrement => Constants::POSTFIX,
 
         TokenType::LParen => Constants::CALL,
         TokenType::LBracket => Constants::INDEX,
@@ -40,62 +71,423 @@ fn get_precedence(token: &TokenType) -> Constants {
     };
 }
 
-pub struct Parser {
-    lexer: Lexer,
-    errors: Vec<String>,
+// The arithmetic operator behind a compound assignment like `+=`
+fn compound_operator(token: &TokenType) -> Option<&'static str> {
+    return match token {
+        TokenType::PlusAssign => Some("+"),
+        TokenType::MinusAssign => Some("-"),
+        TokenType::MultAssign => Some("*"),
+        TokenType::DivAssign => Some("/"),
+        TokenType::ModAssign => Some("%"),
+        _ => None,
+    };
+}
+
+/// Something the parser could not make sense of. Errors from the lexer are
+/// passed through as `Lex` so a single list covers the whole input.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseError {
+    UnexpectedToken {
+        expected: Vec<TokenType>,
+        found: TokenType,
+        span: Span,
+    },
+    NoPrefixParser {
+        token: TokenType,
+        span: Span,
+    },
+    InvalidInteger {
+        literal: String,
+        radix: Radix,
+        span: Span,
+    },
+    InvalidFloat {
+        literal: String,
+        span: Span,
+    },
+    // Points at the `{` that was never closed
+    UnterminatedBlock {
+        span: Span,
+    },
+    InvalidAssignmentTarget {
+        target: String,
+        operator: TokenType,
+        span: Span,
+    },
+    InvalidUpdateTarget {
+        target: String,
+        operator: TokenType,
+        span: Span,
+    },
+    // `in_function` is set when the keyword is in a function defined inside
+    // a loop, which it can't reach
+    LoopControlOutsideLoop {
+        keyword: TokenType,
+        span: Span,
+        in_function: bool,
+    },
+    // `declared` points at the name in the `let const` statement
+    AssignToConstant {
+        name: String,
+        span: Span,
+        declared: Span,
+    },
+    RedeclaredConstant {
+        name: String,
+        span: Span,
+        declared: Span,
+    },
+    Lex(LexError),
+}
+
+impl ParseError {
+    pub fn span(&self) -> Span {
+        return match self {
+            ParseError::UnexpectedToken { span, .. } => *span,
+            ParseError::NoPrefixParser { span, .. } => *span,
+            ParseError::InvalidInteger { span, .. } => *span,
+            ParseError::InvalidFloat { span, .. } => *span,
+            ParseError::UnterminatedBlock { span } => *span,
+            ParseError::InvalidAssignmentTarget { span, .. } => *span,
+            ParseError::InvalidUpdateTarget { span, .. } => *span,
+            ParseError::LoopControlOutsideLoop { span, .. } => *span,
+            ParseError::AssignToConstant { span, .. } => *span,
+            ParseError::RedeclaredConstant { span, .. } => *span,
+            ParseError::Lex(err) => err.span(),
+        };
+    }
+
+    /// The error description without its location.
+    pub fn message(&self) -> String {
+        return match self {
+            ParseError::UnexpectedToken {
+                expected, found, ..
+            } => {
+                let expected = expected
+                    .iter()
+                    .map(|token_type| token_type.to_string())
+                    .collect::<Vec<_>>();
+                let expected = match expected.split_last() {
+                    Some((last, [])) => last.clone(),
+                    Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+                    None => "nothing".to_string(),
+                };
+                format!("Expected next token to be {}, got {}", expected, found)
+            }
+            ParseError::NoPrefixParser { token, .. } => {
+                format!("No prefix parse function for {} found", token)
+            }
+            ParseError::InvalidInteger { literal, radix, .. } => {
+                format!("Could not parse {} as {} integer", literal, radix)
+            }
+            ParseError::InvalidFloat { literal, .. } => {
+                format!("Could not parse {} as float", literal)
+            }
+            ParseError::UnterminatedBlock { .. } => format!(
+                "Expected {} to close block, got {}",
+                TokenType::RBrace,
+                TokenType::Eof
+            ),
+            ParseError::InvalidAssignmentTarget {
+                target, operator, ..
+            } => format!(
+                "Cannot assign to {} with {}, expected an identifier or index expression",
+                target, operator
+            ),
+            ParseError::InvalidUpdateTarget {
+                target, operator, ..
+            } => format!(
+                "Cannot apply {} to {}, expected an identifier or index expression",
+                operator, target
+            ),
+            ParseError::LoopControlOutsideLoop { keyword, .. } => {
+                format!("{} outside of a loop", keyword)
+            }
+            ParseError::AssignToConstant { name, declared, .. } => {
+                format!("Cannot modify constant {} declared at {}", name, declared)
+            }
+            ParseError::RedeclaredConstant { name, declared, .. } => {
+                format!(
+                    "Cannot redeclare constant {} declared at {}",
+                    name, declared
+                )
+            }
+            ParseError::Lex(err) => err.message(),
+        };
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.span(), self.message())
+    }
+}
 
-    cur_token: Token,
-    peek_token: Token,
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        return match self {
+            ParseError::Lex(err) => Some(err),
+            _ => None,
+        };
+    }
+}
 
-    infix_parse_fns: HashMap<TokenType, InfixParseFn>,
-    prefix_parse_fns: HashMap<TokenType, PrefixParseFn>,
+// A scope as far as constants go: the names declared in it, mapped to the
+// span of their `let const` or None if they can be reassigned, and how many
+// `if` bodies deep into it parsing is. Declarations in those bodies might
+// never run, so they can't be relied on. Loop bodies get a scope of their
+// own instead, as each iteration runs in a fresh environment.
+#[derive(Default)]
+struct Scope {
+    bindings: HashMap<String, Option<Span>>,
+    conditional: usize,
 }
 
-impl Parser {
-    pub fn new(mut lexer: Lexer) -> Parser {
-        let cur_token = lexer.next_token();
-        let peek_token = lexer.next_token();
+/// Parses the tokens of a `Lexer` into AST nodes. Tokens are detached from
+/// the source as they are read, so the nodes it builds own their data.
+pub struct Parser<'a> {
+    lexer: Lexer<'a>,
+    errors: Vec<ParseError>,
+    // How many lexer errors have been copied into `errors` so far, and how
+    // many the lexer had recorded once it produced `peek_token`
+    lex_errors: usize,
+    peek_lex_errors: usize,
+    // How many loops enclose the current token within the current function,
+    // so `break` and `continue` outside of one can be rejected
+    loop_depth: usize,
+    // Whether a loop encloses the function the current token is in
+    function_in_loop: bool,
+    // Mirrors the scopes the evaluator creates, so writes to a constant can
+    // be caught before the program runs
+    scopes: Vec<Scope>,
+
+    cur_token: OwnedToken,
+    peek_token: OwnedToken,
 
-        let parser = Parser {
+    infix_parse_fns: HashMap<TokenType, InfixParseFn<'a>>,
+    prefix_parse_fns: HashMap<TokenType, PrefixParseFn<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(lexer: Lexer<'a>) -> Parser<'a> {
+        let mut parser = Parser {
             lexer,
             errors: Vec::new(),
+            lex_errors: 0,
+            peek_lex_errors: 0,
+            loop_depth: 0,
+            function_in_loop: false,
+            scopes: vec![Scope::default()],
 
-            cur_token,
-            peek_token,
+            cur_token: OwnedToken::new(TokenType::Eof, "\0"),
+            peek_token: OwnedToken::new(TokenType::Eof, "\0"),
 
             infix_parse_fns: HashMap::new(),
             prefix_parse_fns: HashMap::new(),
         };
 
+        parser.register_prefix(TokenType::Ident, |parser| {
+            Some(AST::Expression::Identifier(parser.parse_identifier()))
+        });
+        parser.register_prefix(TokenType::Int, |parser| {
+            parser.parse_integer_literal().map(AST::Expression::Integer)
+        });
+        parser.register_prefix(TokenType::Float, |parser| {
+            parser.parse_float_literal().map(AST::Expression::Float)
+        });
+        parser.register_prefix(TokenType::String, |parser| {
+            Some(AST::Expression::String(parser.parse_string_literal()))
+        });
+        parser.register_prefix(TokenType::True, |parser| {
+            Some(AST::Expression::Boolean(parser.parse_boolean()))
+        });
+        parser.register_prefix(TokenType::False, |parser| {
+            Some(AST::Expression::Boolean(parser.parse_boolean()))
+        });
+        parser.register_prefix(TokenType::Bang, Parser::parse_prefix_expression);
+        parser.register_prefix(TokenType::Minus, Parser::parse_prefix_expression);
+        parser.register_prefix(TokenType::BitNot, Parser::parse_prefix_expression);
+        parser.register_prefix(TokenType::Increment, Parser::parse_prefix_update);
+        parser.register_prefix(TokenType::Decrement, Parser::parse_prefix_update);
+        parser.register_prefix(TokenType::LParen, Parser::parse_grouped_expression);
+        parser.register_prefix(TokenType::If, Parser::parse_if_expression);
+        parser.register_prefix(TokenType::Function, Parser::parse_function_literal);
+        parser.register_prefix(TokenType::LBracket, Parser::parse_array_literal);
+        parser.register_prefix(TokenType::LBrace, Parser::parse_hash_literal);
+
+        for token_type in [
+            TokenType::Or,
+            TokenType::And,
+            TokenType::Eq,
+            TokenType::NotEq,
+            TokenType::LessThan,
+            TokenType::GreaterThan,
+            TokenType::LessEq,
+            TokenType::GreaterEq,
+            TokenType::BitOr,
+            TokenType::BitXor,
+            TokenType::BitAnd,
+            TokenType::ShiftLeft,
+            TokenType::ShiftRight,
+            TokenType::Plus,
+            TokenType::Minus,
+            TokenType::Mult,
+            TokenType::Div,
+            TokenType::Mod,
+            TokenType::Pow,
+        ] {
+            parser.register_infix(token_type, Parser::parse_infix_expression);
+        }
+        parser.register_infix(TokenType::Increment, Parser::parse_postfix_update);
+        parser.register_infix(TokenType::Decrement, Parser::parse_postfix_update);
+        parser.register_infix(TokenType::LParen, Parser::parse_call_expression);
+        parser.register_infix(TokenType::LBracket, Parser::parse_index_expression);
+
+        parser.next_token();
+        parser.next_token();
+
         return parser;
     }
 
+    /// Parses the whole input. Statements that fail to parse are skipped,
+    /// so the program holds everything else and `errors` every problem found.
+    pub fn parse_program(&mut self) -> AST::Program {
+        let mut program = AST::Program::new(Vec::new());
+
+        while !self.cur_token_is(&TokenType::Eof) {
+            match self.parse_statement() {
+                Some(statement) => program.statements.push(statement),
+                None => {
+                    self.synchronize();
+                }
+            }
+            self.next_token();
+        }
+
+        return program;
+    }
+
+    pub fn errors(&self) -> &[ParseError] {
+        return &self.errors;
+    }
+
     // Logging
-    fn peek_err(&mut self, expected_type: &TokenType) {
-        self.errors.push(format!(
-            "Expected next token to be {}, got {}",
-            expected_type, self.peek_token.token_type
-        ));
+    fn peek_err(&mut self, expected: &[TokenType]) {
+        self.errors.push(ParseError::UnexpectedToken {
+            expected: expected.to_vec(),
+            found: self.peek_token.token_type.clone(),
+            span: self.peek_token.span,
+        });
     }
 
-    fn no_prefix_parse_fn_err(&mut self, expected_type: &TokenType) {
-        self.errors.push(format!(
-            "No prefix parse function for {} found",
-            expected_type
-        ));
+    fn no_prefix_parse_fn_err(&mut self) {
+        // The lexer has already reported whatever made the token illegal
+        if self.cur_token_is(&TokenType::Illegal) {
+            return;
+        }
+
+        self.errors.push(ParseError::NoPrefixParser {
+            token: self.cur_token.token_type.clone(),
+            span: self.cur_token.span,
+        });
+    }
+
+    // Constants
+    fn scope(&mut self) -> &mut Scope {
+        return self
+            .scopes
+            .last_mut()
+            .expect("the global scope is never popped");
+    }
+
+    fn declare(&mut self, name: &AST::Identifier, constant: bool) {
+        if let Some(Some(declared)) = self.scope().bindings.get(&name.value) {
+            let declared = *declared;
+            self.errors.push(ParseError::RedeclaredConstant {
+                name: name.value.clone(),
+                span: name.token.span,
+                declared,
+            });
+            return;
+        }
+
+        let scope = self.scope();
+        let binding = match constant && scope.conditional == 0 {
+            true => Some(name.token.span),
+            false => None,
+        };
+        scope.bindings.insert(name.value.clone(), binding);
+    }
+
+    // Reports a write to `target` if it definitely goes through a constant
+    fn check_mutable(&mut self, target: &AST::Expression) {
+        let Some(name) = target.root_identifier() else {
+            return;
+        };
+
+        let binding = self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.bindings.get(&name.value));
+
+        if let Some(Some(declared)) = binding {
+            self.errors.push(ParseError::AssignToConstant {
+                name: name.value.clone(),
+                span: name.token.span,
+                declared: *declared,
+            });
+        }
     }
 
     // Utility
-    fn expect_peek(&mut self, token: &Token) -> bool {
-        if self.peek_token.token_type == token.token_type {
+    fn cur_token_is(&self, token_type: &TokenType) -> bool {
+        return self.cur_token.token_type == *token_type;
+    }
+
+    fn peek_token_is(&self, token_type: &TokenType) -> bool {
+        return self.peek_token.token_type == *token_type;
+    }
+
+    fn expect_peek(&mut self, token_type: &TokenType) -> bool {
+        if self.peek_token.token_type == *token_type {
             self.next_token();
             return true;
         } else {
-            self.peek_err(&token.token_type);
+            self.peek_err(std::slice::from_ref(token_type));
             return false;
         }
     }
 
+    // Recovers from a statement that failed to parse by skipping to its end:
+    // a `;`, a `}` closing a block opened along the way, or just before a `}`
+    // closing the enclosing block. Returns true if the current token is
+    // already that enclosing `}`.
+    fn synchronize(&mut self) -> bool {
+        let mut depth = 0;
+
+        loop {
+            match self.cur_token.token_type {
+                TokenType::Eof => return false,
+                TokenType::Semicolon if depth == 0 => return false,
+                TokenType::LBrace => depth += 1,
+                TokenType::RBrace if depth == 0 => return true,
+                TokenType::RBrace => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return false;
+                    }
+                }
+                _ => {}
+            }
+
+            if depth == 0 && self.peek_token_is(&TokenType::RBrace) {
+                return false;
+            }
+            self.next_token();
+        }
+    }
+
     fn peek_precedence(&self) -> Constants {
         return get_precedence(&self.peek_token.token_type);
     }
@@ -105,17 +497,31 @@ impl Parser {
     }
 
     fn next_token(&mut self) {
-        self.cur_token = replace(&mut self.peek_token, self.lexer.next_token());
+        // Report the lexer errors found while reading the token that is about
+        // to become current, so they land in source order among parse errors
+        let lex_errors = &self.lexer.errors()[self.lex_errors..self.peek_lex_errors];
+        self.errors
+            .extend(lex_errors.iter().cloned().map(ParseError::Lex));
+        self.lex_errors = self.peek_lex_errors;
+
+        // The lexer stops after `Eof`, so keep repeating it from there on
+        let next = match self.lexer.next() {
+            Some(token) => token.into_owned(),
+            None => self.peek_token.clone(),
+        };
+        self.peek_lex_errors = self.lexer.errors().len();
+
+        self.cur_token = replace(&mut self.peek_token, next);
     }
 
-		// Registering
-    fn register_prefix(&mut self, token_type: TokenType, fn_: PrefixParseFn) {
+    // Registering
+    fn register_prefix(&mut self, token_type: TokenType, fn_: PrefixParseFn<'a>) {
         self.prefix_parse_fns.insert(token_type, fn_);
     }
 
-		fn register_infix(&mut self, token_type: TokenType, fn_: InfixParseFn) {
-				self.infix_parse_fns.insert(token_type, fn_);
-		}
+    fn register_infix(&mut self, token_type: TokenType, fn_: InfixParseFn<'a>) {
+        self.infix_parse_fns.insert(token_type, fn_);
+    }
 
     // Parsers
     fn parse_boolean(&mut self) -> AST::BooleanLiteral {
@@ -125,11 +531,1331 @@ impl Parser {
         );
     }
 
-		fn parse_string_literal(&mut self) -> AST::StringLiteral {
-				let clone = self.cur_token.clone();
-				return AST::StringLiteral::new(
-						clone,
-						&clone.literal
-				)
-		}
+    fn parse_integer_literal(&mut self) -> Option<AST::IntegerLiteral> {
+        let (radix, _) = Radix::split(&self.cur_token.literal);
+
+        return match parse_int(&self.cur_token.literal) {
+            Some(value) => Some(AST::IntegerLiteral::new(
+                self.cur_token.clone(),
+                value,
+                radix,
+            )),
+            None => {
+                self.errors.push(ParseError::InvalidInteger {
+                    literal: self.cur_token.literal.to_string(),
+                    radix,
+                    span: self.cur_token.span,
+                });
+                None
+            }
+        };
+    }
+
+    fn parse_float_literal(&mut self) -> Option<AST::FloatLiteral> {
+        return match self.cur_token.literal.replace('_', "").parse::<f64>() {
+            Ok(value) => Some(AST::FloatLiteral::new(self.cur_token.clone(), value)),
+            Err(_) => {
+                self.errors.push(ParseError::InvalidFloat {
+                    literal: self.cur_token.literal.to_string(),
+                    span: self.cur_token.span,
+                });
+                None
+            }
+        };
+    }
+
+    fn parse_string_literal(&mut self) -> AST::StringLiteral {
+        return AST::StringLiteral::new(self.cur_token.clone(), self.cur_token.literal.to_string());
+    }
+
+    fn parse_identifier(&mut self) -> AST::Identifier {
+        return AST::Identifier::new(self.cur_token.clone(), self.cur_token.literal.to_string());
+    }
+
+    fn parse_statement(&mut self) -> Option<AST::Statement> {
+        return match self.cur_token.token_type {
+            TokenType::Let => self.parse_let_statement(),
+            TokenType::Return => self.parse_return_statement(),
+            TokenType::For => self.parse_for_statement(),
+            TokenType::While => self.parse_while_statement(),
+            TokenType::Break | TokenType::Continue => self.parse_loop_control(),
+            _ => self.parse_expression_statement(),
+        };
+    }
+
+    fn parse_let_statement(&mut self) -> Option<AST::Statement> {
+        let token = self.cur_token.clone();
+
+        let constant = self.peek_token_is(&TokenType::Constant);
+        if constant {
+            self.next_token();
+        }
+
+        if !self.expect_peek(&TokenType::Ident) {
+            return None;
+        }
+        let name = self.parse_identifier();
+
+        if !self.expect_peek(&TokenType::Assign) {
+            return None;
+        }
+
+        self.next_token();
+        let value = self.parse_expression(Constants::LOWEST)?;
+
+        if self.peek_token_is(&TokenType::Semicolon) {
+            self.next_token();
+        }
+
+        self.declare(&name, constant);
+
+        return Some(AST::Statement::Let(AST::LetStatement::new(
+            token, name, value, constant,
+        )));
+    }
+
+    fn parse_return_statement(&mut self) -> Option<AST::Statement> {
+        let token = self.cur_token.clone();
+
+        self.next_token();
+        let return_value = self.parse_expression(Constants::LOWEST)?;
+
+        if self.peek_token_is(&TokenType::Semicolon) {
+            self.next_token();
+        }
+
+        return Some(AST::Statement::Return(AST::ReturnStatement::new(
+            token,
+            return_value,
+        )));
+    }
+
+    // Parses a loop body in a scope of its own, holding `variables` and
+    // whatever one iteration declares
+    fn parse_loop_body(&mut self, variables: &[&AST::Identifier]) -> AST::BlockStatement {
+        self.loop_depth += 1;
+        self.scopes.push(Scope::default());
+        for name in variables {
+            self.declare(name, false);
+        }
+        let body = self.parse_block_statement();
+        self.scopes.pop();
+        self.loop_depth -= 1;
+
+        return body;
+    }
+
+    // Parses an `if` body, which may not run at all
+    fn parse_conditional_block(&mut self) -> AST::BlockStatement {
+        self.scope().conditional += 1;
+        let body = self.parse_block_statement();
+        self.scope().conditional -= 1;
+
+        return body;
+    }
+
+    fn parse_while_statement(&mut self) -> Option<AST::Statement> {
+        let token = self.cur_token.clone();
+
+        self.next_token();
+        let condition = self.parse_expression(Constants::LOWEST)?;
+
+        if !self.expect_peek(&TokenType::LBrace) {
+            return None;
+        }
+        let body = self.parse_loop_body(&[]);
+
+        return Some(AST::Statement::While(AST::WhileStatement::new(
+            token, condition, body,
+        )));
+    }
+
+    fn parse_loop_control(&mut self) -> Option<AST::Statement> {
+        let token = self.cur_token.clone();
+
+        if self.peek_token_is(&TokenType::Semicolon) {
+            self.next_token();
+        }
+
+        if self.loop_depth == 0 {
+            self.errors.push(ParseError::LoopControlOutsideLoop {
+                keyword: token.token_type.clone(),
+                span: token.span,
+                in_function: self.function_in_loop,
+            });
+            return None;
+        }
+
+        return Some(match token.token_type {
+            TokenType::Break => AST::Statement::Break(AST::BreakStatement::new(token)),
+            _ => AST::Statement::Continue(AST::ContinueStatement::new(token)),
+        });
+    }
+
+    fn parse_for_statement(&mut self) -> Option<AST::Statement> {
+        let token = self.cur_token.clone();
+
+        if !self.expect_peek(&TokenType::LParen) {
+            return None;
+        }
+
+        // The loop variables live in a scope of their own, around the scope
+        // of each iteration's body
+        self.scopes.push(Scope::default());
+        let statement = self.parse_for_clauses(token);
+        self.scopes.pop();
+
+        return statement;
+    }
+
+    // Parses both loop forms after the `(`. They can only be told apart once
+    // a leading identifier is followed by `in` or `,`, so that identifier is
+    // already the current token when the C-style init statement is parsed.
+    fn parse_for_clauses(&mut self, token: OwnedToken) -> Option<AST::Statement> {
+        let init = if self.peek_token_is(&TokenType::Semicolon) {
+            self.next_token();
+            None
+        } else {
+            self.next_token();
+            if self.cur_token_is(&TokenType::Ident)
+                && (self.peek_token_is(&TokenType::In) || self.peek_token_is(&TokenType::Comma))
+            {
+                return self.parse_for_in_statement(token);
+            }
+
+            let init = self.parse_statement()?;
+            if !self.cur_token_is(&TokenType::Semicolon) && !self.expect_peek(&TokenType::Semicolon)
+            {
+                return None;
+            }
+            Some(Box::new(init))
+        };
+
+        let condition = if self.peek_token_is(&TokenType::Semicolon) {
+            self.next_token();
+            None
+        } else {
+            self.next_token();
+            let condition = self.parse_expression(Constants::LOWEST)?;
+            if !self.expect_peek(&TokenType::Semicolon) {
+                return None;
+            }
+            Some(condition)
+        };
+
+        let update = if self.peek_token_is(&TokenType::RParen) {
+            self.next_token();
+            None
+        } else {
+            self.next_token();
+            let update = self.parse_statement()?;
+            if !self.expect_peek(&TokenType::RParen) {
+                return None;
+            }
+            Some(Box::new(update))
+        };
+
+        if !self.expect_peek(&TokenType::LBrace) {
+            return None;
+        }
+        let body = self.parse_loop_body(&[]);
+
+        return Some(AST::Statement::For(AST::ForStatement::new(
+            token, init, condition, update, body,
+        )));
+    }
+
+    // Parses the rest of `for (x in xs)` or `for (k, v in xs)`, starting on
+    // the first identifier
+    fn parse_for_in_statement(&mut self, token: OwnedToken) -> Option<AST::Statement> {
+        let mut key = None;
+        let mut value = self.parse_identifier();
+
+        if self.peek_token_is(&TokenType::Comma) {
+            self.next_token();
+            if !self.expect_peek(&TokenType::Ident) {
+                return None;
+            }
+            key = Some(value);
+            value = self.parse_identifier();
+        }
+
+        if !self.expect_peek(&TokenType::In) {
+            return None;
+        }
+
+        self.next_token();
+        let iterable = self.parse_expression(Constants::LOWEST)?;
+
+        if !self.expect_peek(&TokenType::RParen) || !self.expect_peek(&TokenType::LBrace) {
+            return None;
+        }
+
+        let variables: Vec<_> = key.iter().chain([&value]).collect();
+        let body = self.parse_loop_body(&variables);
+
+        return Some(AST::Statement::ForIn(AST::ForInStatement::new(
+            token, key, value, iterable, body,
+        )));
+    }
+
+    // Also covers assignments, which start out looking like an expression
+    // until the `=` or compound operator after their target
+    fn parse_expression_statement(&mut self) -> Option<AST::Statement> {
+        let token = self.cur_token.clone();
+        let expression = self.parse_expression(Constants::LOWEST)?;
+
+        let statement = if self.peek_token_is(&TokenType::Assign)
+            || compound_operator(&self.peek_token.token_type).is_some()
+        {
+            self.next_token();
+            self.parse_assignment(expression)?
+        } else {
+            AST::Statement::Expression(AST::ExpressionStatement::new(token, expression))
+        };
+
+        if self.peek_token_is(&TokenType::Semicolon) {
+            self.next_token();
+        }
+
+        return Some(statement);
+    }
+
+    // Parses the value of `target = value` or `target op= value`, starting
+    // on the operator. Only identifiers and index expressions are assignable.
+    fn parse_assignment(&mut self, target: AST::Expression) -> Option<AST::Statement> {
+        let token = self.cur_token.clone();
+
+        if !matches!(
+            target,
+            AST::Expression::Identifier(_) | AST::Expression::Index(_)
+        ) {
+            self.errors.push(ParseError::InvalidAssignmentTarget {
+                target: target.to_string(),
+                operator: token.token_type.clone(),
+                span: token.span,
+            });
+            return None;
+        }
+        self.check_mutable(&target);
+
+        self.next_token();
+        let value = self.parse_expression(Constants::LOWEST)?;
+
+        if let Some(operator) = compound_operator(&token.token_type) {
+            return Some(AST::Statement::CompoundAssignment(
+                AST::CompoundAssignmentStatement::new(token, target, operator.to_string(), value),
+            ));
+        }
+
+        return match target {
+            AST::Expression::Identifier(name) => Some(AST::Statement::Assignment(
+                AST::AssignmentStatement::new(token, name, value),
+            )),
+            AST::Expression::Index(index) => {
+                let AST::IndexExpression { left, index, .. } = *index;
+                Some(AST::Statement::IndexAssignment(
+                    AST::ArrayIndexExpression::new(token, left, index, value),
+                ))
+            }
+            _ => unreachable!("assignment targets are checked above"),
+        };
+    }
+
+    fn parse_block_statement(&mut self) -> AST::BlockStatement {
+        let token = self.cur_token.clone();
+        let mut statements = Vec::new();
+
+        self.next_token();
+        while !self.cur_token_is(&TokenType::RBrace) {
+            if self.cur_token_is(&TokenType::Eof) {
+                self.errors
+                    .push(ParseError::UnterminatedBlock { span: token.span });
+                break;
+            }
+
+            match self.parse_statement() {
+                Some(statement) => statements.push(statement),
+                None => {
+                    // The broken statement ran into the end of this block
+                    if self.synchronize() {
+                        break;
+                    }
+                }
+            }
+            self.next_token();
+        }
+
+        return AST::BlockStatement::new(token, statements);
+    }
+
+    fn parse_expression(&mut self, precedence: Constants) -> Option<AST::Expression> {
+        let prefix = match self.prefix_parse_fns.get(&self.cur_token.token_type) {
+            Some(prefix) => *prefix,
+            None => {
+                self.no_prefix_parse_fn_err();
+                return None;
+            }
+        };
+
+        let mut left = prefix(self)?;
+
+        while !self.peek_token_is(&TokenType::Semicolon) && precedence < self.peek_precedence() {
+            let infix = match self.infix_parse_fns.get(&self.peek_token.token_type) {
+                Some(infix) => *infix,
+                None => return Some(left),
+            };
+
+            self.next_token();
+            left = infix(self, left)?;
+        }
+
+        return Some(left);
+    }
+
+    fn parse_prefix_expression(&mut self) -> Option<AST::Expression> {
+        let token = self.cur_token.clone();
+        let operator = token.literal.to_string();
+
+        self.next_token();
+        let right = self.parse_expression(Constants::PREFIX)?;
+
+        return Some(AST::Expression::Prefix(Box::new(
+            AST::PrefixExpression::new(token, operator, right),
+        )));
+    }
+
+    fn parse_infix_expression(&mut self, left: AST::Expression) -> Option<AST::Expression> {
+        let token = self.cur_token.clone();
+        let operator = token.literal.to_string();
+        let precedence = self.cur_precedence();
+
+        // `**` is right-associative, so let its right operand take another
+        // `**` by parsing it at the level just below POWER
+        let right_precedence = if token.token_type == TokenType::Pow {
+            Constants::PREFIX
+        } else {
+            precedence
+        };
+
+        self.next_token();
+        let right = self.parse_expression(right_precedence)?;
+
+        return Some(AST::Expression::Infix(Box::new(AST::InfixExpression::new(
+            token, operator, right, left,
+        ))));
+    }
+
+    fn parse_prefix_update(&mut self) -> Option<AST::Expression> {
+        let token = self.cur_token.clone();
+
+        self.next_token();
+        let target = self.parse_expression(Constants::PREFIX)?;
+
+        return self.build_update(token, target, true);
+    }
+
+    fn parse_postfix_update(&mut self, target: AST::Expression) -> Option<AST::Expression> {
+        let token = self.cur_token.clone();
+        return self.build_update(token, target, false);
+    }
+
+    fn build_update(
+        &mut self,
+        token: OwnedToken,
+        target: AST::Expression,
+        prefix: bool,
+    ) -> Option<AST::Expression> {
+        if !matches!(
+            target,
+            AST::Expression::Identifier(_) | AST::Expression::Index(_)
+        ) {
+            self.errors.push(ParseError::InvalidUpdateTarget {
+                target: target.to_string(),
+                operator: token.token_type.clone(),
+                span: token.span,
+            });
+            return None;
+        }
+        self.check_mutable(&target);
+
+        let operator = token.literal.to_string();
+        return Some(AST::Expression::Update(Box::new(
+            AST::UpdateExpression::new(token, operator, target, prefix),
+        )));
+    }
+
+    fn parse_grouped_expression(&mut self) -> Option<AST::Expression> {
+        self.next_token();
+        let expression = self.parse_expression(Constants::LOWEST)?;
+
+        if !self.expect_peek(&TokenType::RParen) {
+            return None;
+        }
+
+        return Some(expression);
+    }
+
+    // The condition is a plain expression, so both `if (x) {}` and `if x {}`
+    // are accepted. `else if` chains nest inside the alternative block.
+    fn parse_if_expression(&mut self) -> Option<AST::Expression> {
+        let token = self.cur_token.clone();
+
+        self.next_token();
+        let condition = self.parse_expression(Constants::LOWEST)?;
+
+        if !self.expect_peek(&TokenType::LBrace) {
+            return None;
+        }
+        let consequence = self.parse_conditional_block();
+
+        let mut alternative = None;
+        if self.peek_token_is(&TokenType::Else) {
+            self.next_token();
+
+            if self.peek_token_is(&TokenType::If) {
+                self.next_token();
+                let else_token = self.cur_token.clone();
+                let nested = self.parse_if_expression()?;
+
+                alternative = Some(AST::BlockStatement::new(
+                    else_token.clone(),
+                    vec![AST::Statement::Expression(AST::ExpressionStatement::new(
+                        else_token, nested,
+                    ))],
+                ));
+            } else {
+                if !self.expect_peek(&TokenType::LBrace) {
+                    return None;
+                }
+                alternative = Some(self.parse_conditional_block());
+            }
+        }
+
+        return Some(AST::Expression::If(Box::new(AST::IfExpression::new(
+            token,
+            condition,
+            consequence,
+            alternative,
+        ))));
+    }
+
+    fn parse_function_literal(&mut self) -> Option<AST::Expression> {
+        let token = self.cur_token.clone();
+
+        if !self.expect_peek(&TokenType::LParen) {
+            return None;
+        }
+        let parameters = self.parse_function_parameters()?;
+
+        if !self.expect_peek(&TokenType::LBrace) {
+            return None;
+        }
+
+        // A loop around the function literal does not reach into its body
+        let loop_depth = replace(&mut self.loop_depth, 0);
+        let function_in_loop = self.function_in_loop;
+        self.function_in_loop |= loop_depth > 0;
+        self.scopes.push(Scope::default());
+        for parameter in &parameters {
+            self.declare(parameter, false);
+        }
+        let body = self.parse_block_statement();
+        self.scopes.pop();
+        self.loop_depth = loop_depth;
+        self.function_in_loop = function_in_loop;
+
+        return Some(AST::Expression::Function(Rc::new(
+            AST::FunctionLiteral::new(token, parameters, body),
+        )));
+    }
+
+    fn parse_function_parameters(&mut self) -> Option<Vec<AST::Identifier>> {
+        let mut parameters = Vec::new();
+
+        if self.peek_token_is(&TokenType::RParen) {
+            self.next_token();
+            return Some(parameters);
+        }
+
+        if !self.expect_peek(&TokenType::Ident) {
+            return None;
+        }
+        parameters.push(self.parse_identifier());
+
+        while self.peek_token_is(&TokenType::Comma) {
+            self.next_token();
+            if !self.expect_peek(&TokenType::Ident) {
+                return None;
+            }
+            parameters.push(self.parse_identifier());
+        }
+
+        if !self.expect_peek(&TokenType::RParen) {
+            return None;
+        }
+
+        return Some(parameters);
+    }
+
+    fn parse_call_expression(&mut self, function: AST::Expression) -> Option<AST::Expression> {
+        let token = self.cur_token.clone();
+        let arguments = self.parse_expression_list(&TokenType::RParen)?;
+
+        return Some(AST::Expression::Call(Box::new(AST::CallExpression::new(
+            token,
+            function,
+            Some(arguments),
+        ))));
+    }
+
+    // Parses comma separated expressions up to `end`, starting on the
+    // opening token
+    fn parse_expression_list(&mut self, end: &TokenType) -> Option<Vec<AST::Expression>> {
+        let mut list = Vec::new();
+
+        if self.peek_token_is(end) {
+            self.next_token();
+            return Some(list);
+        }
+
+        self.next_token();
+        list.push(self.parse_expression(Constants::LOWEST)?);
+
+        while self.peek_token_is(&TokenType::Comma) {
+            self.next_token();
+            self.next_token();
+            list.push(self.parse_expression(Constants::LOWEST)?);
+        }
+
+        // Either another element or the end of the list would have done
+        if !self.peek_token_is(end) {
+            self.peek_err(&[TokenType::Comma, end.clone()]);
+            return None;
+        }
+        self.next_token();
+
+        return Some(list);
+    }
+
+    fn parse_array_literal(&mut self) -> Option<AST::Expression> {
+        let token = self.cur_token.clone();
+        let elements = self.parse_expression_list(&TokenType::RBracket)?;
+
+        return Some(AST::Expression::Array(AST::ArrayLiteral::new(
+            token, elements,
+        )));
+    }
+
+    fn parse_index_expression(&mut self, left: AST::Expression) -> Option<AST::Expression> {
+        let token = self.cur_token.clone();
+
+        self.next_token();
+        let index = self.parse_expression(Constants::LOWEST)?;
+
+        if !self.expect_peek(&TokenType::RBracket) {
+            return None;
+        }
+
+        return Some(AST::Expression::Index(Box::new(AST::IndexExpression::new(
+            token, left, index,
+        ))));
+    }
+
+    fn parse_hash_literal(&mut self) -> Option<AST::Expression> {
+        let token = self.cur_token.clone();
+        let mut pairs = Vec::new();
+
+        while !self.peek_token_is(&TokenType::RBrace) {
+            self.next_token();
+            let key = self.parse_expression(Constants::LOWEST)?;
+
+            if !self.expect_peek(&TokenType::Colon) {
+                return None;
+            }
+
+            self.next_token();
+            let value = self.parse_expression(Constants::LOWEST)?;
+
+            pairs.push((key, value));
+
+            if self.peek_token_is(&TokenType::Comma) {
+                self.next_token();
+            } else if !self.peek_token_is(&TokenType::RBrace) {
+                self.peek_err(&[TokenType::Comma, TokenType::RBrace]);
+                return None;
+            }
+        }
+
+        if !self.expect_peek(&TokenType::RBrace) {
+            return None;
+        }
+
+        return Some(AST::Expression::Hash(AST::HashLiteral::new(token, pairs)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokens::Span;
+
+    #[test]
+    fn it_reads_tokens_from_the_lexer() {
+        let mut parser = Parser::new(Lexer::new("let x = 5;"));
+
+        assert_eq!(parser.cur_token.token_type, TokenType::Let);
+        assert_eq!(parser.peek_token.token_type, TokenType::Ident);
+        assert!(parser.expect_peek(&TokenType::Ident));
+        assert_eq!(parser.cur_token.literal, "x");
+        assert_eq!(parser.cur_token.span, Span::new(4, 5, 1, 5));
+
+        assert!(!parser.expect_peek(&TokenType::Semicolon));
+        assert_eq!(
+            parser.errors(),
+            &[ParseError::UnexpectedToken {
+                expected: vec![TokenType::Semicolon],
+                found: TokenType::Assign,
+                span: Span::new(6, 7, 1, 7),
+            }]
+        );
+
+        for _ in 0..5 {
+            parser.next_token();
+        }
+        assert_eq!(parser.cur_token.token_type, TokenType::Eof);
+        assert_eq!(parser.peek_token.token_type, TokenType::Eof);
+    }
+    #[test]
+    fn it_builds_nodes_that_outlive_the_source() {
+        let (string, integer, float, boolean) = {
+            let input = String::from(r#""hi\n" 0xFF 2.5e-3 true"#);
+            let mut parser = Parser::new(Lexer::new(&input));
+
+            let string = parser.parse_string_literal();
+            parser.next_token();
+            let integer = parser.parse_integer_literal().unwrap();
+            parser.next_token();
+            let float = parser.parse_float_literal().unwrap();
+            parser.next_token();
+            let boolean = parser.parse_boolean();
+
+            (string, integer, float, boolean)
+        };
+
+        assert_eq!(string.value, "hi\n");
+        assert_eq!(integer.value, 255);
+        assert_eq!(integer.radix, Radix::Hexadecimal);
+        assert_eq!(integer.to_string(), "0xFF");
+        assert_eq!(float.value, 2.5e-3);
+        assert!(boolean.value);
+    }
+
+    fn messages(parser: &Parser) -> Vec<String> {
+        return parser.errors().iter().map(ParseError::message).collect();
+    }
+
+    fn parse(input: &str) -> AST::Program {
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse_program();
+
+        assert!(
+            parser.errors().is_empty(),
+            "parser errors: {:?}",
+            parser.errors()
+        );
+        return program;
+    }
+
+    #[test]
+    fn it_parses_operator_precedence() {
+        let tests = vec![
+            ("-a * b", "((-a) * b)"),
+            ("!-a", "(!(-a))"),
+            ("a + b * c + d / e - f", "(((a + (b * c)) + (d / e)) - f)"),
+            ("3 + 4; -5 * 5", "(3 + 4)((-5) * 5)"),
+            ("5 > 4 == 3 < 4", "((5 > 4) == (3 < 4))"),
+            ("a <= b && c >= d || e", "(((a <= b) && (c >= d)) || e)"),
+            ("a || b && c", "(a || (b && c))"),
+            ("a & b == c", "((a & b) == c)"),
+            ("a | b ^ c & d", "(a | (b ^ (c & d)))"),
+            ("1 << 2 + 3 >> 1", "((1 << (2 + 3)) >> 1)"),
+            ("a % b * c", "((a % b) * c)"),
+            ("2 ** 3 ** 2", "(2 ** (3 ** 2))"),
+            ("-2 ** 2", "(-(2 ** 2))"),
+            ("2 * 3 ** 2", "(2 * (3 ** 2))"),
+            ("~a & b", "((~a) & b)"),
+            ("true == !false", "(true == (!false))"),
+            ("(5 + 5) * 2", "((5 + 5) * 2)"),
+            ("-(5 + 5)", "(-(5 + 5))"),
+            ("a + add(b * c) + d", "((a + add((b * c))) + d)"),
+            (
+                "add(a, b, 1, 2 * 3, 4 + 5, add(6, 7 * 8))",
+                "add(a, b, 1, (2 * 3), (4 + 5), add(6, (7 * 8)))",
+            ),
+            (
+                "a * [1, 2, 3, 4][b * c] * d",
+                "((a * ([1, 2, 3, 4][(b * c)])) * d)",
+            ),
+            (
+                "add(a * b[2], b[1], 2 * [1, 2][1])",
+                "add((a * (b[2])), (b[1]), (2 * ([1, 2][1])))",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(parse(input).to_string(), expected, "input: {}", input);
+        }
+    }
+    #[test]
+    fn it_parses_literals() {
+        let program = parse(r#"foo; 0b101; 1_000; 2.5; "a\tb"; true; false"#);
+        let statements = &program.statements;
+        assert_eq!(statements.len(), 7);
+
+        let expressions: Vec<&AST::Expression> = statements
+            .iter()
+            .map(|statement| match statement {
+                AST::Statement::Expression(statement) => &statement.expression,
+                _ => panic!("expected an expression statement, got {}", statement),
+            })
+            .collect();
+
+        assert!(
+            matches!(expressions[0], AST::Expression::Identifier(ident) if ident.value == "foo")
+        );
+        assert!(matches!(expressions[1], AST::Expression::Integer(int) if int.value == 5));
+        assert!(matches!(expressions[2], AST::Expression::Integer(int) if int.value == 1000));
+        assert!(matches!(expressions[3], AST::Expression::Float(float) if float.value == 2.5));
+        assert!(
+            matches!(expressions[4], AST::Expression::String(string) if string.value == "a\tb")
+        );
+        assert!(matches!(expressions[5], AST::Expression::Boolean(boolean) if boolean.value));
+        assert!(matches!(expressions[6], AST::Expression::Boolean(boolean) if !boolean.value));
+    }
+    #[test]
+    fn it_parses_if_expressions() {
+        let tests = vec![
+            ("if (x < y) { x }", "if (x < y) { x }"),
+            ("if (x < y) { x } else { y }", "if (x < y) { x } else { y }"),
+            (
+                "if x { 1 } else if y { 2 } else { 3 }",
+                "if x { 1 } else { if y { 2 } else { 3 } }",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(parse(input).to_string(), expected, "input: {}", input);
+        }
+    }
+    #[test]
+    fn it_parses_functions_and_calls() {
+        let tests = vec![
+            ("fn() {}", "fn() {}"),
+            ("fn(x) { x }", "fn(x) {x}"),
+            ("fn(x, y) { x + y; }", "fn(x, y) {(x + y)}"),
+            ("fn(x) { x }(5)", "fn(x) {x}(5)"),
+            ("add()", "add()"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(parse(input).to_string(), expected, "input: {}", input);
+        }
+    }
+    #[test]
+    fn it_parses_arrays_hashes_and_indexes() {
+        let tests = vec![
+            ("[]", "[]"),
+            ("[1, 2 * 2, 3 + 3]", "[1, (2 * 2), (3 + 3)]"),
+            ("arr[1 + 1]", "(arr[(1 + 1)])"),
+            ("{}", "{}"),
+            (
+                r#"{"one": 1, true: 2, 3: x + 1}"#,
+                "{one: 1, true: 2, 3: (x + 1)}",
+            ),
+            (r#"{"a": [1]}["a"][0]"#, "(({a: [1]}[a])[0])"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(parse(input).to_string(), expected, "input: {}", input);
+        }
+    }
+    #[test]
+    fn it_reports_expression_errors() {
+        let tests = vec![
+            ("* 5", "No prefix parse function for * found"),
+            ("(1 + 2", "Expected next token to be ), got EOF"),
+            (
+                "fn(x, 1) {}",
+                "Expected next token to be Identifier, got Integer",
+            ),
+            ("{1 2}", "Expected next token to be :, got Integer"),
+            ("[1 2]", "Expected next token to be , or ], got Integer"),
+            ("f(a b)", "Expected next token to be , or ), got Identifier"),
+            (
+                "{1: 2 3: 4}",
+                "Expected next token to be , or }, got Integer",
+            ),
+            ("if (x) { 1", "Expected } to close block, got EOF"),
+        ];
+
+        for (input, expected) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse_program();
+            assert_eq!(
+                parser.errors().first().map(ParseError::message),
+                Some(expected.to_string()),
+                "input: {}",
+                input
+            );
+        }
+    }
+    #[test]
+    fn it_parses_let_and_return_statements() {
+        let program = parse("let x = 5; let const y = x * 2; return x + y;");
+
+        assert_eq!(program.statements.len(), 3);
+        match &program.statements[1] {
+            AST::Statement::Let(statement) => {
+                assert!(statement.constant);
+                assert_eq!(statement.name.value, "y");
+            }
+            statement => panic!("expected a let statement, got {}", statement),
+        }
+        assert_eq!(
+            program.to_string(),
+            "let x = 5;let const y = (x * 2);return (x + y);"
+        );
+    }
+    #[test]
+    fn it_parses_assignments() {
+        let tests = vec![
+            ("x = 6;", "x = 6"),
+            ("x = y + 1", "x = (y + 1)"),
+            ("arr[i] = v", "(arr[i] = v)"),
+            ("x += 1", "x += 1"),
+            ("x %= 2 * 3", "x %= (2 * 3)"),
+            ("arr[i] -= 1", "(arr[i]) -= 1"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(parse(input).to_string(), expected, "input: {}", input);
+        }
+
+        let program = parse("arr[i + 1] = 2; h[\"k\"] *= 3");
+        assert!(matches!(
+            program.statements[0],
+            AST::Statement::IndexAssignment(_)
+        ));
+        match &program.statements[1] {
+            AST::Statement::CompoundAssignment(statement) => {
+                assert_eq!(statement.operator, "*");
+                assert!(matches!(statement.target, AST::Expression::Index(_)));
+            }
+            statement => panic!("expected a compound assignment, got {}", statement),
+        }
+    }
+    #[test]
+    fn it_reports_statement_errors() {
+        let tests = vec![
+            ("let = 5", "Expected next token to be Identifier, got ="),
+            ("let x 5", "Expected next token to be =, got Integer"),
+            (
+                "let const = 5",
+                "Expected next token to be Identifier, got =",
+            ),
+            (
+                "5 = 6",
+                "Cannot assign to 5 with =, expected an identifier or index expression",
+            ),
+            (
+                "f() = 1",
+                "Cannot assign to f() with =, expected an identifier or index expression",
+            ),
+            (
+                "a + b += 1",
+                "Cannot assign to (a + b) with +=, expected an identifier or index expression",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse_program();
+            assert_eq!(
+                parser.errors().first().map(ParseError::message),
+                Some(expected.to_string()),
+                "input: {}",
+                input
+            );
+        }
+    }
+    #[test]
+    fn it_parses_increments_and_decrements() {
+        let tests = vec![
+            ("x++; --x; x--; ++x", "(x++)(--x)(x--)(++x)"),
+            ("a[i]++", "((a[i])++)"),
+            ("--a[i + 1]", "(--(a[(i + 1)]))"),
+            ("-x++", "(-(x++))"),
+            ("x++ + ++y", "((x++) + (++y))"),
+            ("2 ** i++", "(2 ** (i++))"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(parse(input).to_string(), expected, "input: {}", input);
+        }
+
+        match &parse("i++").statements[0] {
+            AST::Statement::Expression(AST::ExpressionStatement {
+                expression: AST::Expression::Update(update),
+                ..
+            }) => {
+                assert!(!update.prefix);
+                assert_eq!(update.operator, "++");
+            }
+            statement => panic!("expected an update expression, got {}", statement),
+        }
+
+        let tests = vec![
+            (
+                "5++",
+                "Cannot apply ++ to 5, expected an identifier or index expression",
+            ),
+            (
+                "--f()",
+                "Cannot apply -- to f(), expected an identifier or index expression",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse_program();
+            assert_eq!(
+                parser.errors().first().map(ParseError::message),
+                Some(expected.to_string()),
+                "input: {}",
+                input
+            );
+        }
+    }
+    #[test]
+    fn it_parses_for_loops() {
+        let tests = vec![
+            (
+                "for (let i = 0; i < n; i++) { x += i; }",
+                "for (let i = 0; (i < n); (i++)) { x += i }",
+            ),
+            (
+                "for (i = 0; i < 10; i += 2) { }",
+                "for (i = 0; (i < 10); i += 2) {  }",
+            ),
+            ("for (;;) { f() }", "for (; ; ) { f() }"),
+            ("for (; i < n;) { i++ }", "for (; (i < n); ) { (i++) }"),
+            ("for (i; i; i) {}", "for (i; i; i) {  }"),
+            ("for (x in arr) { print(x) }", "for (x in arr) { print(x) }"),
+            ("for (k, v in h) { v }", "for (k, v in h) { v }"),
+            ("for (x in [1, 2][0]) {}", "for (x in ([1, 2][0])) {  }"),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(parse(input).to_string(), expected, "input: {}", input);
+        }
+
+        match &parse("for (let i = 0; i < 3; i++) {}").statements[0] {
+            AST::Statement::For(statement) => {
+                assert!(matches!(
+                    statement.init.as_deref(),
+                    Some(AST::Statement::Let(_))
+                ));
+                assert!(matches!(
+                    statement.condition,
+                    Some(AST::Expression::Infix(_))
+                ));
+                assert!(matches!(
+                    statement.update.as_deref(),
+                    Some(AST::Statement::Expression(_))
+                ));
+            }
+            statement => panic!("expected a for statement, got {}", statement),
+        }
+        match &parse("for (k, v in h) {}").statements[0] {
+            AST::Statement::ForIn(statement) => {
+                assert_eq!(statement.key.as_ref().unwrap().value, "k");
+                assert_eq!(statement.value.value, "v");
+            }
+            statement => panic!("expected a for-in statement, got {}", statement),
+        }
+
+        let tests = vec![
+            (
+                "for x in arr {}",
+                "Expected next token to be (, got Identifier",
+            ),
+            (
+                "for (k, 1 in h) {}",
+                "Expected next token to be Identifier, got Integer",
+            ),
+            (
+                "for (let i = 0; i < n) {}",
+                "Expected next token to be ;, got )",
+            ),
+            (
+                "for (x in arr) x",
+                "Expected next token to be {, got Identifier",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse_program();
+            assert_eq!(
+                parser.errors().first().map(ParseError::message),
+                Some(expected.to_string()),
+                "input: {}",
+                input
+            );
+        }
+    }
+    #[test]
+    fn it_parses_while_loops() {
+        let tests = vec![
+            ("while (x < 10) { x++ }", "while (x < 10) { (x++) }"),
+            (
+                "while true { if done { break; } continue }",
+                "while true { if done { break; }continue; }",
+            ),
+            (
+                "for (x in xs) { if x { continue; } }",
+                "for (x in xs) { if x { continue; } }",
+            ),
+            (
+                "while a { while b { break } break }",
+                "while a { while b { break; }break; }",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            assert_eq!(parse(input).to_string(), expected, "input: {}", input);
+        }
+
+        let tests = vec![
+            ("break;", "break outside of a loop"),
+            ("if x { continue }", "continue outside of a loop"),
+            (
+                "while x { let f = fn() { break; }; }",
+                "break outside of a loop",
+            ),
+            ("while x { } break", "break outside of a loop"),
+        ];
+
+        for (input, expected) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse_program();
+            assert_eq!(messages(&parser), vec![expected], "input: {}", input);
+        }
+
+        // Only a function inside a loop hides that loop from its body
+        for (input, in_loop) in [
+            ("break", false),
+            ("let f = fn() { continue }", false),
+            ("while x { let f = fn() { break } }", true),
+            ("while x { let f = fn() { fn() { break } } }", true),
+            ("let f = fn() { while x { } break }", false),
+        ] {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse_program();
+            assert!(
+                matches!(
+                    parser.errors(),
+                    [ParseError::LoopControlOutsideLoop { in_function, .. }] if *in_function == in_loop
+                ),
+                "input: {}",
+                input
+            );
+        }
+    }
+    #[test]
+    fn it_recovers_from_errors() {
+        let input = r#"
+            let x = 5;
+            let = 10;
+            let y 3;
+            let z = 1;
+            let f = fn() { let a = ; a };
+            if (x { y }
+            x + * 2;
+            while x { z = ; break }
+            z
+        "#;
+        let mut parser = Parser::new(Lexer::new(input));
+        let program = parser.parse_program();
+
+        assert_eq!(
+            messages(&parser),
+            vec![
+                "Expected next token to be Identifier, got =",
+                "Expected next token to be =, got Integer",
+                "No prefix parse function for ; found",
+                "Expected next token to be ), got {",
+                "No prefix parse function for * found",
+                "No prefix parse function for ; found",
+            ]
+        );
+        assert_eq!(
+            program.to_string(),
+            "let x = 5;let z = 1;let f = fn() {a};while x { break; }z"
+        );
+    }
+    #[test]
+    fn it_recovers_at_the_end_of_a_block() {
+        let mut parser = Parser::new(Lexer::new("if x { y = } z; }"));
+        let program = parser.parse_program();
+
+        assert_eq!(
+            messages(&parser),
+            vec![
+                "No prefix parse function for } found",
+                "No prefix parse function for } found",
+            ]
+        );
+        assert_eq!(program.to_string(), "if x {  }z");
+    }
+    #[test]
+    fn it_reports_typed_errors() {
+        let mut parser = Parser::new(Lexer::new("let x = [1, 2\nlet y = 0x;\nif (y) { @"));
+        parser.parse_program();
+        let errors = parser.errors();
+
+        assert_eq!(
+            errors[0],
+            ParseError::UnexpectedToken {
+                expected: vec![TokenType::Comma, TokenType::RBracket],
+                found: TokenType::Let,
+                span: Span::new(14, 17, 2, 1),
+            }
+        );
+        assert!(matches!(
+            errors[1],
+            ParseError::Lex(LexError::InvalidNumber { .. })
+        ));
+        assert!(matches!(
+            errors[2],
+            ParseError::Lex(LexError::UnexpectedCharacter { ch: '@', .. })
+        ));
+        assert_eq!(
+            errors[3],
+            ParseError::UnterminatedBlock {
+                span: Span::new(33, 34, 3, 8),
+            }
+        );
+        assert_eq!(errors.len(), 4);
+
+        assert_eq!(
+            errors[3].to_string(),
+            "3:8: Expected } to close block, got EOF"
+        );
+        assert!(errors[1].source().is_some());
+        assert!(errors[0].source().is_none());
+    }
+    #[test]
+    fn it_rejects_writes_to_constants() {
+        let tests = vec![
+            (
+                "let const x = 1; x = 2",
+                "Cannot modify constant x declared at 1:11",
+            ),
+            (
+                "let const x = 1; x += 2",
+                "Cannot modify constant x declared at 1:11",
+            ),
+            (
+                "let const x = 1; x++",
+                "Cannot modify constant x declared at 1:11",
+            ),
+            (
+                "let const a = [1]; --a[0]",
+                "Cannot modify constant a declared at 1:11",
+            ),
+            (
+                "let const a = [[1]];\na[0][0] = 2",
+                "Cannot modify constant a declared at 1:11",
+            ),
+            (
+                "let const x = 1; let f = fn() { if y { x = 2 } }",
+                "Cannot modify constant x declared at 1:11",
+            ),
+            (
+                "let const x = 1; while y { for (i in z) { x *= i } }",
+                "Cannot modify constant x declared at 1:11",
+            ),
+            (
+                "let const x = 1; let x = 2",
+                "Cannot redeclare constant x declared at 1:11",
+            ),
+            (
+                "let const x = 1; if y { let const x = 2 }",
+                "Cannot redeclare constant x declared at 1:11",
+            ),
+            (
+                "while y { let const c = 1; c = 2 }",
+                "Cannot modify constant c declared at 1:21",
+            ),
+        ];
+
+        for (input, expected) in tests {
+            let mut parser = Parser::new(Lexer::new(input));
+            parser.parse_program();
+            assert_eq!(messages(&parser), vec![expected], "input: {}", input);
+        }
+
+        let mut parser = Parser::new(Lexer::new("let const x = 1;\nx = 2;"));
+        parser.parse_program();
+        assert_eq!(
+            parser.errors(),
+            &[ParseError::AssignToConstant {
+                name: "x".to_string(),
+                span: Span::new(17, 18, 2, 1),
+                declared: Span::new(10, 11, 1, 11),
+            }]
+        );
+
+        // Shadowed, or not known to be constant where it's written
+        for input in [
+            "let const x = 1; let f = fn(x) { x = 2 }",
+            "let const x = 1; for (x in y) { x = 2 }",
+            "let const i = 1; for (let i = 0; i < 2; i++) {}",
+            "let const x = 1; let f = fn() { let x = 2; x = 3 }",
+            "let f = fn() { x = 2 }; let const x = 1",
+            "if y { let const x = 1 } x = 2",
+            "let x = 1; let const x = 2",
+            "let const a = [1]; let b = a; b[0] = 2",
+            "while y { let const c = 1 } let c = 2",
+            "for (let i = 0; i < 2; i++) { let const c = i }",
+        ] {
+            parse(input);
+        }
+    }
+    #[test]
+    fn it_lists_lexer_and_parser_errors_in_source_order() {
+        let mut parser = Parser::new(Lexer::new("let x = * @;\nlet y = 1 $"));
+        parser.parse_program();
+
+        assert_eq!(
+            messages(&parser),
+            vec![
+                "No prefix parse function for * found",
+                "unexpected character `@`",
+                "unexpected character `$`",
+            ]
+        );
+        let spans = parser
+            .errors()
+            .iter()
+            .map(|err| err.span().start)
+            .collect::<Vec<_>>();
+        assert!(
+            spans.windows(2).all(|pair| pair[0] <= pair[1]),
+            "{:?}",
+            spans
+        );
+    }
 }
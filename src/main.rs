@@ -1,21 +1,40 @@
+use diagnostics::{render_all, Diagnostic};
+use evaluator::{eval, Environment, Object};
 use lexer::Lexer;
-use std::{io, process};
+use parser::Parser;
+use std::io::{self, IsTerminal};
+use std::process;
 
 fn main() {
-		println!("Monkey v0.1.0 RLPL:");
+		println!("Monkey v0.1.0 RPPL:");
+		let env = Environment::new();
 		loop {
 				let mut input = String::new();
 
-				io::stdin().read_line(&mut input).unwrap();
+				// Stop once stdin is closed or can no longer be read
+				match io::stdin().read_line(&mut input) {
+						Ok(0) | Err(_) => break,
+						Ok(_) => {}
+				}
 				let input = input.trim();
 
 				if input == "exit" {
 						process::exit(0);
 				}
 
-				let lexer = Lexer::new(input);
-				for token in lexer {
-					println!("{:?}", token);
+				let mut parser = Parser::new(Lexer::new(input));
+				let program = parser.parse_program();
+
+				if !parser.errors().is_empty() {
+						let diagnostics = parser.errors().iter().map(Diagnostic::from).collect::<Vec<_>>();
+						let colour = io::stdout().is_terminal();
+						print!("{}", render_all("<repl>", input, &diagnostics, colour));
+						continue;
+				}
+
+				match eval(&program, &env) {
+						Object::Null => {}
+						result => println!("{}", result),
 				}
 		}
 }
@@ -1,4 +1,6 @@
-use std::fmt;
+#![allow(clippy::needless_return)]
+
+use std::{borrow::Cow, fmt};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum TokenType {
@@ -7,16 +9,37 @@ pub enum TokenType {
 
     Ident,
     Int,
+    Float,
 
     Assign,
+    PlusAssign,
+    MinusAssign,
+    MultAssign,
+    DivAssign,
+    ModAssign,
+
     Plus,
     Minus,
     Mult,
     Div,
+    Mod,
+    Pow,
     Bang,
 
+    BitAnd,
+    BitOr,
+    BitXor,
+    BitNot,
+    ShiftLeft,
+    ShiftRight,
+
     LessThan,
     GreaterThan,
+    LessEq,
+    GreaterEq,
+
+    And,
+    Or,
 
     Comma,
     Semicolon,
@@ -31,6 +54,10 @@ pub enum TokenType {
     RBracket,
 
     For,
+    In,
+    While,
+    Break,
+    Continue,
     Function,
     Let,
     True,
@@ -56,14 +83,32 @@ impl fmt::Display for TokenType {
             TokenType::Eof => "EOF",
             TokenType::Ident => "Identifier",
             TokenType::Int => "Integer",
+            TokenType::Float => "Float",
             TokenType::Assign => "=",
+            TokenType::PlusAssign => "+=",
+            TokenType::MinusAssign => "-=",
+            TokenType::MultAssign => "*=",
+            TokenType::DivAssign => "/=",
+            TokenType::ModAssign => "%=",
             TokenType::Plus => "+",
             TokenType::Minus => "-",
             TokenType::Mult => "*",
             TokenType::Div => "/",
+            TokenType::Mod => "%",
+            TokenType::Pow => "**",
             TokenType::Bang => "!",
+            TokenType::BitAnd => "&",
+            TokenType::BitOr => "|",
+            TokenType::BitXor => "^",
+            TokenType::BitNot => "~",
+            TokenType::ShiftLeft => "<<",
+            TokenType::ShiftRight => ">>",
             TokenType::LessThan => "<",
             TokenType::GreaterThan => ">",
+            TokenType::LessEq => "<=",
+            TokenType::GreaterEq => ">=",
+            TokenType::And => "&&",
+            TokenType::Or => "||",
             TokenType::Comma => ",",
             TokenType::Semicolon => ";",
             TokenType::Colon => ":",
@@ -74,6 +119,10 @@ impl fmt::Display for TokenType {
             TokenType::LBracket => "[",
             TokenType::RBracket => "]",
             TokenType::For => "for",
+            TokenType::In => "in",
+            TokenType::While => "while",
+            TokenType::Break => "break",
+            TokenType::Continue => "continue",
             TokenType::Function => "function",
             TokenType::Let => "let",
             TokenType::True => "true",
@@ -93,27 +142,143 @@ impl fmt::Display for TokenType {
     }
 }
 
+/// Location of a token in the source text.
+///
+/// `start` and `end` are byte offsets into the input, `line` and `column` are
+/// 1-based and point at the first character. A zeroed span means the location
+/// is unknown, e.g. for tokens built by hand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize, line: usize, column: usize) -> Self {
+        Self {
+            start,
+            end,
+            line,
+            column,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// A lexed token. `literal` borrows from the source where it can, string
+/// tokens containing escape sequences own their unescaped value instead.
 #[derive(Debug, Clone, PartialEq)]
 pub struct Token<'a> {
     pub token_type: TokenType,
-    pub literal: &'a str,
+    pub literal: Cow<'a, str>,
+    pub span: Span,
 }
 
-impl Token<'_> {
-    pub fn new<'a>(token_type: TokenType, literal: &'a str) -> Token<'a> {
+impl<'a> Token<'a> {
+    pub fn new(token_type: TokenType, literal: impl Into<Cow<'a, str>>) -> Token<'a> {
         return Token {
             token_type,
-            literal: literal,
+            literal: literal.into(),
+            span: Span::default(),
+        };
+    }
+
+    pub fn with_span(self, span: Span) -> Self {
+        Self { span, ..self }
+    }
+
+    /// Detaches the token from the source text it was lexed from.
+    pub fn into_owned(self) -> OwnedToken {
+        return Token {
+            token_type: self.token_type,
+            literal: Cow::Owned(self.literal.into_owned()),
+            span: self.span,
         };
     }
 }
 
+/// A token that owns its literal, as stored in the AST so it can outlive the
+/// source it was parsed from.
+pub type OwnedToken = Token<'static>;
+
 impl fmt::Display for Token<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}", self.literal)
     }
 }
 
+/// The base an integer literal is written in, picked by its `0b`, `0o` or
+/// `0x` prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Radix {
+    Binary,
+    Octal,
+    Decimal,
+    Hexadecimal,
+}
+
+impl Radix {
+    /// Splits an integer literal into its radix and the digits after the prefix.
+    pub fn split(literal: &str) -> (Radix, &str) {
+        return match literal.get(..2) {
+            Some("0b") => (Radix::Binary, &literal[2..]),
+            Some("0o") => (Radix::Octal, &literal[2..]),
+            Some("0x") => (Radix::Hexadecimal, &literal[2..]),
+            _ => (Radix::Decimal, literal),
+        };
+    }
+
+    pub fn value(self) -> u32 {
+        return match self {
+            Radix::Binary => 2,
+            Radix::Octal => 8,
+            Radix::Decimal => 10,
+            Radix::Hexadecimal => 16,
+        };
+    }
+}
+
+impl fmt::Display for Radix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let radix_str = match self {
+            Radix::Binary => "binary",
+            Radix::Octal => "octal",
+            Radix::Decimal => "decimal",
+            Radix::Hexadecimal => "hexadecimal",
+        };
+
+        write!(f, "{}", radix_str)
+    }
+}
+
+/// Parses an integer literal in any radix, skipping `_` separators. Returns
+/// None if it has no digits, has digits invalid for its radix or overflows `i64`.
+pub fn parse_int(literal: &str) -> Option<i64> {
+    let (radix, digits) = Radix::split(literal);
+    let digits: String = digits.chars().filter(|ch| *ch != '_').collect();
+
+    if !digits.chars().all(|ch| ch.is_digit(radix.value())) {
+        return None;
+    }
+
+    return i64::from_str_radix(&digits, radix.value()).ok();
+}
+
 pub fn lookup_keyword(ident: &str) -> TokenType {
     return match ident {
         "fn" => TokenType::Function,
@@ -124,6 +289,10 @@ pub fn lookup_keyword(ident: &str) -> TokenType {
         "else" => TokenType::Else,
         "return" => TokenType::Return,
         "for" => TokenType::For,
+        "in" => TokenType::In,
+        "while" => TokenType::While,
+        "break" => TokenType::Break,
+        "continue" => TokenType::Continue,
         "const" => TokenType::Constant,
         _ => TokenType::Ident, // Should never occur
     };